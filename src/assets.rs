@@ -0,0 +1,127 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::file_assist;
+
+/// Font compiled into the binary, so rslush starts from any directory.
+pub const FONT: &[u8] = include_bytes!("Roboto.ttf");
+
+/// Color schemes shipped with rslush, used when no file of the same name is installed.
+const THEMES: &[(&str, &str)] = &[
+    ("blue.json", include_str!("../colors/blue.json")),
+    ("default.json", include_str!("../colors/default.json")),
+    ("empty.json", include_str!("../colors/empty.json")),
+    ("green.json", include_str!("../colors/green.json")),
+    ("light.json", include_str!("../colors/light.json")),
+    ("oceanic.json", include_str!("../colors/oceanic.json")),
+];
+
+pub const DEFAULT_THEME: &str = "oceanic.json";
+
+/// `share/rslush` next to the `bin` directory of the executable.
+fn install_dir() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.parent()?.join("share/rslush"))
+}
+
+/// TTF file of `font`, given as a path or as a family name looked up with fontconfig.
+pub fn font_file(font: &str) -> Result<PathBuf, String> {
+    if Path::new(font).is_file() {
+        return Ok(PathBuf::from(font));
+    }
+    let output = Command::new("fc-match")
+        .args(["--format=%{file}", font])
+        .output()
+        .map_err(|e| format!("Unable to find font {}: {}", font, e))?;
+    let file = PathBuf::from(String::from_utf8_lossy(&output.stdout).to_string());
+    match output.status.success() && file.is_file() {
+        true => Ok(file),
+        false => Err(format!("Font not found: {}", font)),
+    }
+}
+
+/// Directories searched for color schemes, the user's first.
+fn theme_dirs() -> Vec<PathBuf> {
+    let user = file_assist::config_dir().map(|dir| dir.join("colors"));
+    let installed = install_dir().map(|dir| dir.join("colors"));
+    user.into_iter().chain(installed).collect()
+}
+
+fn json_name(name: &str) -> String {
+    match name.ends_with(".json") {
+        true => name.to_string(),
+        false => format!("{}.json", name),
+    }
+}
+
+/// Contents of a color scheme given as a path or as a name like `oceanic`.
+pub fn theme_text(theme: &str) -> Result<String, String> {
+    if Path::new(theme).is_file() {
+        return fs::read_to_string(theme).map_err(|e| format!("Unable to read {}: {}", theme, e));
+    }
+    // also accepts the paths of older sessions, e.g. ./colors/oceanic.json
+    let name = Path::new(theme)
+        .file_name()
+        .map_or_else(|| theme.to_string(), |n| json_name(&n.to_string_lossy()));
+    find_theme(&theme_dirs(), &name).ok_or_else(|| format!("Color scheme not found: {}", theme))
+}
+
+fn find_theme(dirs: &[PathBuf], name: &str) -> Option<String> {
+    let installed = dirs
+        .iter()
+        .find_map(|dir| fs::read_to_string(dir.join(name)).ok());
+    installed.or_else(|| {
+        THEMES
+            .iter()
+            .find(|(theme, _)| *theme == name)
+            .map(|(_, text)| text.to_string())
+    })
+}
+
+/// Names of all color schemes, installed or bundled.
+pub fn list_themes() -> Vec<String> {
+    list_themes_in(&theme_dirs())
+}
+
+fn list_themes_in(dirs: &[PathBuf]) -> Vec<String> {
+    let mut names: Vec<String> = dirs
+        .iter()
+        .flat_map(|dir| file_assist::list_files(dir, |e| e.ends_with(".json")))
+        .chain(THEMES.iter().map(|(name, _)| name.to_string()))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_theme_test() {
+        let dir = std::env::temp_dir().join(format!("rslush-assets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("mine.json"), "{}").unwrap();
+        fs::write(dir.join("blue.json"), "{\"mine\": 1}").unwrap();
+        let dirs = [dir];
+        assert_eq!(Some(String::from("{}")), find_theme(&dirs, "mine.json"));
+        assert_eq!(
+            Some(String::from("{\"mine\": 1}")),
+            find_theme(&dirs, "blue.json")
+        );
+        assert!(find_theme(&dirs, "oceanic.json").is_some());
+        assert_eq!(None, find_theme(&dirs, "missing.json"));
+        let names = list_themes_in(&dirs);
+        assert_eq!(1, names.iter().filter(|n| *n == "blue.json").count());
+        assert!(names.contains(&String::from("mine.json")));
+    }
+
+    #[test]
+    fn theme_text_test() {
+        assert!(theme_text("oceanic").is_ok());
+        assert!(theme_text("./colors/light.json").is_ok());
+        assert!(theme_text("no-such-theme").is_err());
+    }
+}
@@ -1,15 +1,38 @@
+use crate::encoding::Encoding;
+use crate::file_format::{FileFormat, LineEnding};
+use crate::format;
+use crate::increment;
+use crate::text_object::{Pos, Range};
+use crate::undo::{Change, History};
+
 pub struct Buffer {
     pub file_name: String,
     pub lines: Vec<String>,
     pub cursor: Cursor,
     pub modified: bool,
+    /// Saving needs `:w!`, e.g. for files without write permission.
+    pub read_only: bool,
+    pub format: FileFormat,
+    pub history: History,
+    /// Lines as of the last undo step.
+    committed: Vec<String>,
+    /// Cursor when the change in progress began, see `begin_change`.
+    change_start: Option<(usize, usize)>,
 }
 
+#[derive(Clone, Copy, PartialEq)]
 pub struct Cursor {
     pub row: u32,
     pub col: u32,
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Case {
+    Lower,
+    Upper,
+    Toggle,
+}
+
 pub enum Direction {
     Left,
     Right,
@@ -19,22 +42,106 @@ pub enum Direction {
 
 impl Buffer {
     pub fn new(text: String, file_name: String) -> Buffer {
-        let lines = text.lines().map(|l| l.to_string()).collect();
+        let lines = split_lines(&text);
         Buffer {
             file_name,
+            committed: lines.clone(),
             lines,
             cursor: Cursor { row: 0, col: 0 },
             modified: false,
+            read_only: false,
+            format: FileFormat::default(),
+            history: History::default(),
+            change_start: None,
         }
     }
 
     pub fn update(&mut self, text: String, file_name: &String) {
-        self.lines = text.lines().map(|l| l.to_string()).collect();
+        self.lines = split_lines(&text);
         self.file_name = file_name.clone();
         self.cursor = Cursor { row: 0, col: 0 };
+        self.modified = false;
+        self.read_only = false;
+        self.format = FileFormat::default();
+        self.history = History::default();
+        self.committed = self.lines.clone();
+        self.change_start = None;
+    }
+
+    fn cursor_tuple(&self) -> (usize, usize) {
+        (self.cursor.row as usize, self.cursor.col as usize)
+    }
+
+    /// Starts an undo step, unless one is already in progress. Everything changed
+    /// until `end_change` is undone at once.
+    pub fn begin_change(&mut self) {
+        if self.change_start.is_none() {
+            self.change_start = Some(self.cursor_tuple());
+        }
+    }
+
+    /// Records the lines changed since `begin_change` as one undo step.
+    pub fn end_change(&mut self) {
+        if let Some(before) = self.change_start.take() {
+            let after = self.cursor_tuple();
+            if let Some(change) = Change::between(&self.committed, &self.lines, before, after) {
+                change.apply(&mut self.committed);
+                self.history.push(change);
+            }
+        }
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.end_change();
+        let change = match self.history.undo() {
+            Some(change) => change,
+            None => return false,
+        };
+        change.revert(&mut self.lines);
+        change.revert(&mut self.committed);
+        let (row, col) = change.before;
+        self.set_cursor_pos(Pos::new(row, col));
+        self.modified = !self.history.is_saved();
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.end_change();
+        let change = match self.history.redo() {
+            Some(change) => change,
+            None => return false,
+        };
+        change.apply(&mut self.lines);
+        change.apply(&mut self.committed);
+        let (row, col) = change.after;
+        self.set_cursor_pos(Pos::new(row, col));
+        self.modified = !self.history.is_saved();
+        true
+    }
+
+    /// Converts the file to `encoding` on the next save. UTF-16 gets a BOM, the
+    /// only way it is recognized when the file is opened again.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        if self.format.encoding != encoding {
+            self.format.encoding = encoding;
+            if let Encoding::Utf16Le | Encoding::Utf16Be = encoding {
+                self.format.bom = true;
+            }
+            self.modified = true;
+        }
+    }
+
+    /// Changes the line endings written on the next save.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.format.line_ending != line_ending {
+            self.format.line_ending = line_ending;
+            self.modified = true;
+        }
     }
 
     pub fn saved(&mut self) {
+        self.end_change();
+        self.history.mark_saved();
         self.modified = false;
     }
 
@@ -44,14 +151,17 @@ impl Buffer {
 
     pub fn move_cursor(&mut self, dir: Direction) {
         match dir {
+            // by whole characters, the column is a byte offset
             Direction::Left => {
-                if self.cursor.col > 0 {
-                    self.cursor.col -= 1
+                let line = &self.lines[self.cursor.row as usize];
+                if let Some(prev) = line[..self.cursor.col as usize].chars().next_back() {
+                    self.cursor.col -= prev.len_utf8() as u32
                 }
             }
             Direction::Right => {
-                if self.cursor.col < self.lines[self.cursor.row as usize].len() as u32 {
-                    self.cursor.col += 1
+                let line = &self.lines[self.cursor.row as usize];
+                if let Some(next) = line[self.cursor.col as usize..].chars().next() {
+                    self.cursor.col += next.len_utf8() as u32
                 }
             }
             Direction::Up => {
@@ -65,10 +175,8 @@ impl Buffer {
                 }
             }
         }
-        let max_col = self.lines[self.cursor.row as usize].len() as u32;
-        if self.cursor.col > max_col {
-            self.cursor.col = max_col;
-        }
+        let line = &self.lines[self.cursor.row as usize];
+        self.cursor.col = char_boundary(line, self.cursor.col as usize) as u32;
     }
 
     pub fn enter_newline(&mut self) {
@@ -106,8 +214,325 @@ impl Buffer {
         if self.cursor.col < self.lines[self.cursor.row as usize].len() as u32 {
             let line = self.lines.get_mut(self.cursor.row as usize).unwrap();
             let (l, r) = line.split_at(self.cursor.col as usize);
-            self.lines[self.cursor.row as usize] = format!("{}{}", l, &r[1..]);
+            let width = r.chars().next().map_or(0, |c| c.len_utf8());
+            self.lines[self.cursor.row as usize] = format!("{}{}", l, &r[width..]);
             self.modified = true;
         }
     }
+
+    /// Overwrites the character under the cursor, or appends at the end of the line,
+    /// and returns what was overwritten.
+    pub fn replace_char(&mut self, c: char) -> Option<char> {
+        let (row, col) = (self.cursor.row as usize, self.cursor.col as usize);
+        let line = &mut self.lines[row];
+        let old = line[col..].chars().next();
+        let old_len = old.map_or(0, |o| o.len_utf8());
+        line.replace_range(col..col + old_len, c.encode_utf8(&mut [0; 4]));
+        self.cursor.col += c.len_utf8() as u32;
+        self.modified = true;
+        old
+    }
+
+    /// Undoes `replace_char` for the character left of the cursor.
+    pub fn restore_char(&mut self, old: Option<char>) {
+        let (row, col) = (self.cursor.row as usize, self.cursor.col as usize);
+        let line = &mut self.lines[row];
+        if let Some(prev) = line[..col].chars().next_back() {
+            let start = col - prev.len_utf8();
+            match old {
+                Some(o) => line.replace_range(start..col, o.encode_utf8(&mut [0; 4])),
+                None => line.replace_range(start..col, ""),
+            }
+            self.cursor.col = start as u32;
+        }
+    }
+
+    /// Replaces `count` characters from the cursor with `c`, leaving the cursor on the
+    /// last one. Does nothing when the line is too short, like vim.
+    pub fn replace_chars(&mut self, c: char, count: usize) -> bool {
+        let pos = self.cursor_pos();
+        let line = &self.lines[pos.row];
+        let chars: Vec<(usize, char)> = line[pos.col..].char_indices().take(count).collect();
+        if chars.len() < count || count == 0 {
+            return false;
+        }
+        let end = pos.col + chars[count - 1].0 + chars[count - 1].1.len_utf8();
+        let text: String = std::iter::repeat_n(c, count).collect();
+        self.replace_range(Range::new(pos, Pos::new(pos.row, end)), &text);
+        self.set_cursor_pos(Pos::new(pos.row, pos.col + text.len() - c.len_utf8()));
+        true
+    }
+
+    pub fn change_case(&mut self, range: Range, case: Case) {
+        let text = self.text_range(range);
+        let changed = with_case(&text, case);
+        if changed != text {
+            self.replace_range(range, &changed);
+        }
+    }
+
+    /// Toggles the case of `count` characters and moves past them (`~`).
+    pub fn toggle_case(&mut self, count: usize) {
+        let pos = self.cursor_pos();
+        let len: usize = self.lines[pos.row][pos.col..]
+            .chars()
+            .take(count)
+            .map(|c| c.len_utf8())
+            .sum();
+        let range = Range::new(pos, Pos::new(pos.row, pos.col + len));
+        let toggled = with_case(&self.text_range(range), Case::Toggle);
+        self.replace_range(range, &toggled);
+        let line = &self.lines[pos.row];
+        let end = pos.col + toggled.len();
+        let col = if end < line.len() {
+            end
+        } else {
+            line.char_indices().next_back().map_or(0, |(i, _)| i)
+        };
+        self.set_cursor_pos(Pos::new(pos.row, col));
+    }
+
+    /// Joins `count` lines starting at the cursor (at least two), see `format::join`.
+    pub fn join_lines(&mut self, count: usize, spaces: bool) {
+        let row = self.cursor.row as usize;
+        let mut col = self.cursor.col as usize;
+        for _ in 1..usize::max(count, 2) {
+            if row + 1 >= self.lines.len() {
+                break;
+            }
+            let next = self.lines.remove(row + 1);
+            let (joined, at) = format::join(&self.lines[row], &next, spaces);
+            self.lines[row] = joined;
+            col = at;
+            self.modified = true;
+        }
+        self.set_cursor_pos(Pos::new(row, col));
+    }
+
+    /// Replaces rows `first..=last` with `new_lines`.
+    pub fn replace_lines(&mut self, first: usize, last: usize, new_lines: Vec<String>) {
+        self.lines.splice(first..=last, new_lines);
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+        self.modified = true;
+        let row = usize::min(self.cursor.row as usize, self.lines.len() - 1);
+        self.set_cursor_pos(Pos::new(row, self.cursor.col as usize));
+    }
+
+    /// Adds `delta` to the number at or after `pos`, returning where the number ends.
+    pub fn increment_number(&mut self, pos: Pos, delta: i64) -> Option<Pos> {
+        let (line, col) = increment::increment(&self.lines[pos.row], pos.col, delta)?;
+        self.lines[pos.row] = line;
+        self.modified = true;
+        Some(Pos::new(pos.row, col))
+    }
+
+    pub fn cursor_pos(&self) -> Pos {
+        Pos::new(self.cursor.row as usize, self.cursor.col as usize)
+    }
+
+    pub fn set_cursor_pos(&mut self, pos: Pos) {
+        let row = usize::min(pos.row, self.lines.len() - 1);
+        let col = char_boundary(&self.lines[row], pos.col);
+        self.cursor = Cursor {
+            row: row as u32,
+            col: col as u32,
+        };
+    }
+
+    pub fn text_range(&self, range: Range) -> String {
+        if range.start.row == range.end.row {
+            return self.lines[range.start.row][range.start.col..range.end.col].to_string();
+        }
+        let mut text = self.lines[range.start.row][range.start.col..].to_string();
+        for l in &self.lines[range.start.row + 1..range.end.row] {
+            text.push('\n');
+            text += l;
+        }
+        text.push('\n');
+        text += &self.lines[range.end.row][..range.end.col];
+        text
+    }
+
+    /// Replaces `range` with `text`, which may span several lines.
+    pub fn replace_range(&mut self, range: Range, text: &str) {
+        let prefix = &self.lines[range.start.row][..range.start.col];
+        let suffix = &self.lines[range.end.row][range.end.col..];
+        let joined = format!("{}{}{}", prefix, text, suffix);
+        let new_lines: Vec<String> = joined.split('\n').map(|l| l.to_string()).collect();
+        self.lines
+            .splice(range.start.row..=range.end.row, new_lines);
+        self.modified = true;
+    }
+}
+
+/// Lines of `text`, split on `\n` only so a stray `\r` stays part of its line.
+fn split_lines(text: &str) -> Vec<String> {
+    text.split('\n').map(|l| l.to_string()).collect()
+}
+
+/// `col` moved back onto the line and to the start of the character it falls in.
+fn char_boundary(line: &str, col: usize) -> usize {
+    let mut col = usize::min(col, line.len());
+    while !line.is_char_boundary(col) {
+        col -= 1;
+    }
+    col
+}
+
+fn with_case(text: &str, case: Case) -> String {
+    match case {
+        Case::Lower => text.to_lowercase(),
+        Case::Upper => text.to_uppercase(),
+        Case::Toggle => text
+            .chars()
+            .flat_map(|c| {
+                if c.is_lowercase() {
+                    c.to_uppercase().collect::<Vec<char>>()
+                } else {
+                    c.to_lowercase().collect::<Vec<char>>()
+                }
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_assist;
+
+    fn buffer(text: &str) -> Buffer {
+        Buffer::new(text.to_string(), String::from("test"))
+    }
+
+    #[test]
+    fn empty_buffer_test() {
+        assert_eq!(vec![String::new()], buffer("").lines);
+    }
+
+    #[test]
+    fn replace_and_restore_test() {
+        let mut b = buffer("ab");
+        let old: Vec<Option<char>> = "xyz".chars().map(|c| b.replace_char(c)).collect();
+        assert_eq!("xyz", b.lines[0]);
+        assert_eq!(vec![Some('a'), Some('b'), None], old);
+        for o in old.into_iter().rev() {
+            b.restore_char(o);
+        }
+        assert_eq!("ab", b.lines[0]);
+        assert_eq!(0, b.cursor.col);
+    }
+
+    #[test]
+    fn multibyte_test() {
+        let mut b = buffer("");
+        "aąß".chars().for_each(|c| b.insert_char(c));
+        assert_eq!(5, b.cursor.col);
+        b.move_cursor(Direction::Left);
+        b.delete_current_character();
+        assert_eq!(vec![String::from("aą")], b.lines);
+        b.move_cursor(Direction::Left);
+        assert_eq!(1, b.cursor.col);
+    }
+
+    #[test]
+    fn replace_chars_test() {
+        let mut b = buffer("abcd");
+        assert!(b.replace_chars('-', 3));
+        assert_eq!("---d", b.lines[0]);
+        assert_eq!(2, b.cursor.col);
+        assert!(!b.replace_chars('-', 5));
+        assert_eq!("---d", b.lines[0]);
+    }
+
+    #[test]
+    fn join_lines_test() {
+        let mut b = buffer("fn f(\n    a,\n    b\n)\nnext");
+        b.join_lines(4, true);
+        assert_eq!(vec!["fn f( a, b)", "next"], b.lines);
+        assert_eq!(10, b.cursor.col);
+        b.set_cursor_pos(Pos::new(0, 0));
+        b.join_lines(1, false);
+        assert_eq!(vec!["fn f( a, b)next"], b.lines);
+    }
+
+    #[test]
+    fn case_test() {
+        let mut b = buffer("Hello World");
+        b.toggle_case(3);
+        assert_eq!("hELlo World", b.lines[0]);
+        assert_eq!(3, b.cursor.col);
+        b.change_case(Range::new(Pos::new(0, 6), Pos::new(0, 11)), Case::Upper);
+        assert_eq!("hELlo WORLD", b.lines[0]);
+        b.set_cursor_pos(Pos::new(0, 10));
+        b.toggle_case(4);
+        assert_eq!("hELlo WORLd", b.lines[0]);
+        assert_eq!(10, b.cursor.col);
+    }
+
+    #[test]
+    fn move_cursor_multibyte_test() {
+        let mut b = buffer("abcdef\nzäö");
+        b.set_cursor_pos(Pos::new(0, 4));
+        b.move_cursor(Direction::Down);
+        assert_eq!(3, b.cursor.col);
+        b.insert_char('x');
+        assert_eq!("zäxö", b.lines[1]);
+        b.set_cursor_pos(Pos::new(1, 2));
+        assert_eq!(1, b.cursor.col);
+    }
+
+    #[test]
+    fn set_encoding_reopen_test() {
+        let path = std::env::temp_dir().join(format!("rslush-fenc-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        for encoding in [Encoding::Utf16Le, Encoding::Utf16Be] {
+            let mut b = buffer("zażółć\ngęślą");
+            b.set_encoding(encoding);
+            file_assist::save_file(path, &b.lines, &b.format).unwrap();
+            let opened = file_assist::open_file(path).unwrap();
+            assert_eq!(
+                (encoding, "zażółć\ngęślą"),
+                (opened.format.encoding, opened.text.as_str())
+            );
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn undo_test() {
+        let mut b = buffer("one\ntwo");
+        b.begin_change();
+        b.join_lines(1, true);
+        b.end_change();
+        assert_eq!(vec!["one two"], b.lines);
+        assert!(b.undo());
+        assert_eq!(vec!["one", "two"], b.lines);
+        assert!(!b.undo());
+        assert!(b.redo());
+        assert_eq!(vec!["one two"], b.lines);
+        assert!(b.modified);
+        assert!(b.undo());
+        assert!(!b.modified);
+    }
+
+    #[test]
+    fn undo_unchanged_test() {
+        let mut b = buffer("one\ntwo");
+        b.begin_change();
+        b.move_cursor(Direction::Down);
+        b.end_change();
+        assert!(!b.undo());
+        b.begin_change();
+        b.insert_char('x');
+        b.end_change();
+        b.saved();
+        assert!(b.undo());
+        assert_eq!(vec!["one", "two"], b.lines);
+        assert!(b.modified);
+        assert!(b.redo());
+        assert!(!b.modified);
+    }
 }
@@ -0,0 +1,203 @@
+use std::path::Path;
+
+pub const USAGE: &str = "Usage: rslush [options] [+N] [file[:line[:col]] ...]
+       rslush -d file1 file2
+       command | rslush -
+
+Options:
+  +N               start at line N of the first file, + alone for the last line
+  -R               open files read-only
+  -d               show the differences between two files
+  -                read the buffer from stdin
+  --theme <name>   color scheme, a path or a name like oceanic
+  --config <file>  use this config file
+  --check-themes [theme ...]
+                   report problems in the given or all color schemes and exit
+  --import-theme <file> [name]
+                   convert a VS Code, TextMate or base16 theme and exit
+  --version        print the version and exit
+  --help           print this help and exit";
+
+/// File to open, with the 0-based position to put the cursor at.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FileArg {
+    pub path: String,
+    pub row: Option<usize>,
+    pub col: Option<usize>,
+}
+
+/// Row used for `+` without a number: the last line.
+pub const LAST_ROW: usize = usize::MAX;
+
+#[derive(PartialEq, Debug, Default)]
+pub struct Args {
+    pub files: Vec<FileArg>,
+    pub stdin: bool,
+    pub read_only: bool,
+    pub diff: bool,
+    pub theme: Option<String>,
+    pub config: Option<String>,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Cli {
+    Run(Args),
+    /// Print the text and exit, for `--help` and `--version`.
+    Exit(String),
+    /// Check the color schemes, all of them when none are given.
+    CheckThemes(Vec<String>),
+    /// Import the theme file, as a color scheme with the given name if any.
+    ImportTheme(String, Option<String>),
+}
+
+fn line_number(text: &str) -> Option<usize> {
+    text.parse::<usize>().ok().map(|n| n.saturating_sub(1))
+}
+
+/// Splits `file:line[:col]`, unless a file with that exact name exists.
+fn file_arg(arg: &str) -> FileArg {
+    let at = |path: &str, row, col| FileArg {
+        path: path.to_string(),
+        row,
+        col,
+    };
+    if Path::new(arg).exists() {
+        return at(arg, None, None);
+    }
+    // reversed: col, line, path
+    let parts: Vec<&str> = arg.rsplitn(3, ':').collect();
+    let numbers: Vec<Option<usize>> = parts.iter().map(|p| line_number(p)).collect();
+    match (parts.as_slice(), numbers.as_slice()) {
+        ([_, _, path], [Some(col), Some(row), _]) if !path.is_empty() => {
+            at(path, Some(*row), Some(*col))
+        }
+        ([_, _], [Some(row), _]) | ([_, _, _], [Some(row), None, _]) => {
+            let (path, _) = arg.rsplit_once(':').unwrap();
+            at(path, Some(*row), None)
+        }
+        _ => at(arg, None, None),
+    }
+}
+
+/// Parses the arguments after the program name.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Cli, String> {
+    let mut parsed = Args::default();
+    let mut jump: Option<usize> = None;
+    let mut args = args.into_iter();
+    let mut only_files = false;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", name))
+        };
+        match arg.as_str() {
+            _ if only_files => parsed.files.push(file_arg(&arg)),
+            "--" => only_files = true,
+            "--help" | "-h" => return Ok(Cli::Exit(USAGE.to_string())),
+            "--version" | "-v" => {
+                return Ok(Cli::Exit(format!("rslush {}", env!("CARGO_PKG_VERSION"))))
+            }
+            "--check-themes" => return Ok(Cli::CheckThemes(args.collect())),
+            "--import-theme" => {
+                let file = value("--import-theme")?;
+                return Ok(Cli::ImportTheme(file, args.next()));
+            }
+            "-R" => parsed.read_only = true,
+            "-d" => parsed.diff = true,
+            "-" => parsed.stdin = true,
+            "--theme" => parsed.theme = Some(value("--theme")?),
+            "--config" => parsed.config = Some(value("--config")?),
+            "+" => jump = Some(LAST_ROW),
+            _ if arg.starts_with("--theme=") => parsed.theme = Some(arg[8..].to_string()),
+            _ if arg.starts_with("--config=") => parsed.config = Some(arg[9..].to_string()),
+            _ if arg.starts_with('+') => match line_number(&arg[1..]) {
+                Some(row) => jump = Some(row),
+                None => return Err(format!("Invalid line number: {}", arg)),
+            },
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ => parsed.files.push(file_arg(&arg)),
+        }
+    }
+    if let (Some(row), Some(first)) = (jump, parsed.files.first_mut()) {
+        first.row = Some(row);
+        first.col = None;
+    }
+    if parsed.diff && parsed.files.len() != 2 {
+        return Err(String::from("-d needs exactly two files"));
+    }
+    if parsed.stdin && !parsed.files.is_empty() {
+        return Err(String::from(
+            "Can't read stdin and open files at the same time",
+        ));
+    }
+    Ok(Cli::Run(parsed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Args {
+        match parse(args.iter().map(|a| a.to_string())) {
+            Ok(Cli::Run(args)) => args,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn file(path: &str, row: Option<usize>, col: Option<usize>) -> FileArg {
+        FileArg {
+            path: path.to_string(),
+            row,
+            col,
+        }
+    }
+
+    #[test]
+    fn files_test() {
+        let args = run(&["+12", "a.rs", "b.rs:3:7", "c.rs:5", "--", "-d"]);
+        assert_eq!(
+            vec![
+                file("a.rs", Some(11), None),
+                file("b.rs", Some(2), Some(6)),
+                file("c.rs", Some(4), None),
+                file("-d", None, None),
+            ],
+            args.files
+        );
+        assert_eq!(
+            vec![file("x", Some(LAST_ROW), None)],
+            run(&["x", "+"]).files
+        );
+        assert_eq!(vec![file("a:b", None, None)], run(&["a:b"]).files);
+        assert_eq!(vec![file("a:b", Some(1), None)], run(&["a:b:2"]).files);
+    }
+
+    #[test]
+    fn options_test() {
+        let args = run(&["-R", "--theme", "gruvbox", "--config=rc.json", "f"]);
+        assert!(args.read_only);
+        assert_eq!(Some(String::from("gruvbox")), args.theme);
+        assert_eq!(Some(String::from("rc.json")), args.config);
+        assert!(run(&["-"]).stdin);
+        assert!(run(&["-d", "a", "b"]).diff);
+    }
+
+    #[test]
+    fn errors_test() {
+        let parse = |args: &[&str]| parse(args.iter().map(|a| a.to_string()));
+        assert!(parse(&["-d", "a"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["--theme"]).is_err());
+        assert!(parse(&["+x", "a"]).is_err());
+        assert_eq!(Ok(Cli::Exit(USAGE.to_string())), parse(&["--help"]));
+        assert_eq!(
+            Ok(Cli::CheckThemes(vec![String::from("light")])),
+            parse(&["--check-themes", "light"])
+        );
+        assert_eq!(
+            Ok(Cli::ImportTheme(String::from("a.tmTheme"), None)),
+            parse(&["--import-theme", "a.tmTheme"])
+        );
+        assert!(parse(&["--import-theme"]).is_err());
+    }
+}
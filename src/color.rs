@@ -0,0 +1,355 @@
+use sdl2::pixels::Color;
+use std::collections::BTreeMap;
+
+/// Colors a scheme names in its `palette`, referred to as `$name`.
+pub type Palette = BTreeMap<String, Color>;
+
+/// CSS color names.
+const NAMED: &[(&str, u32)] = &[
+    ("aliceblue", 0xF0F8FF),
+    ("antiquewhite", 0xFAEBD7),
+    ("aqua", 0x00FFFF),
+    ("aquamarine", 0x7FFFD4),
+    ("azure", 0xF0FFFF),
+    ("beige", 0xF5F5DC),
+    ("bisque", 0xFFE4C4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xFFEBCD),
+    ("blue", 0x0000FF),
+    ("blueviolet", 0x8A2BE2),
+    ("brown", 0xA52A2A),
+    ("burlywood", 0xDEB887),
+    ("cadetblue", 0x5F9EA0),
+    ("chartreuse", 0x7FFF00),
+    ("chocolate", 0xD2691E),
+    ("coral", 0xFF7F50),
+    ("cornflowerblue", 0x6495ED),
+    ("cornsilk", 0xFFF8DC),
+    ("crimson", 0xDC143C),
+    ("cyan", 0x00FFFF),
+    ("darkblue", 0x00008B),
+    ("darkcyan", 0x008B8B),
+    ("darkgoldenrod", 0xB8860B),
+    ("darkgray", 0xA9A9A9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xA9A9A9),
+    ("darkkhaki", 0xBDB76B),
+    ("darkmagenta", 0x8B008B),
+    ("darkolivegreen", 0x556B2F),
+    ("darkorange", 0xFF8C00),
+    ("darkorchid", 0x9932CC),
+    ("darkred", 0x8B0000),
+    ("darksalmon", 0xE9967A),
+    ("darkseagreen", 0x8FBC8F),
+    ("darkslateblue", 0x483D8B),
+    ("darkslategray", 0x2F4F4F),
+    ("darkslategrey", 0x2F4F4F),
+    ("darkturquoise", 0x00CED1),
+    ("darkviolet", 0x9400D3),
+    ("deeppink", 0xFF1493),
+    ("deepskyblue", 0x00BFFF),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1E90FF),
+    ("firebrick", 0xB22222),
+    ("floralwhite", 0xFFFAF0),
+    ("forestgreen", 0x228B22),
+    ("fuchsia", 0xFF00FF),
+    ("gainsboro", 0xDCDCDC),
+    ("ghostwhite", 0xF8F8FF),
+    ("gold", 0xFFD700),
+    ("goldenrod", 0xDAA520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xADFF2F),
+    ("grey", 0x808080),
+    ("honeydew", 0xF0FFF0),
+    ("hotpink", 0xFF69B4),
+    ("indianred", 0xCD5C5C),
+    ("indigo", 0x4B0082),
+    ("ivory", 0xFFFFF0),
+    ("khaki", 0xF0E68C),
+    ("lavender", 0xE6E6FA),
+    ("lavenderblush", 0xFFF0F5),
+    ("lawngreen", 0x7CFC00),
+    ("lemonchiffon", 0xFFFACD),
+    ("lightblue", 0xADD8E6),
+    ("lightcoral", 0xF08080),
+    ("lightcyan", 0xE0FFFF),
+    ("lightgoldenrodyellow", 0xFAFAD2),
+    ("lightgray", 0xD3D3D3),
+    ("lightgreen", 0x90EE90),
+    ("lightgrey", 0xD3D3D3),
+    ("lightpink", 0xFFB6C1),
+    ("lightsalmon", 0xFFA07A),
+    ("lightseagreen", 0x20B2AA),
+    ("lightskyblue", 0x87CEFA),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xB0C4DE),
+    ("lightyellow", 0xFFFFE0),
+    ("lime", 0x00FF00),
+    ("limegreen", 0x32CD32),
+    ("linen", 0xFAF0E6),
+    ("magenta", 0xFF00FF),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66CDAA),
+    ("mediumblue", 0x0000CD),
+    ("mediumorchid", 0xBA55D3),
+    ("mediumpurple", 0x9370DB),
+    ("mediumseagreen", 0x3CB371),
+    ("mediumslateblue", 0x7B68EE),
+    ("mediumspringgreen", 0x00FA9A),
+    ("mediumturquoise", 0x48D1CC),
+    ("mediumvioletred", 0xC71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xF5FFFA),
+    ("mistyrose", 0xFFE4E1),
+    ("moccasin", 0xFFE4B5),
+    ("navajowhite", 0xFFDEAD),
+    ("navy", 0x000080),
+    ("oldlace", 0xFDF5E6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6B8E23),
+    ("orange", 0xFFA500),
+    ("orangered", 0xFF4500),
+    ("orchid", 0xDA70D6),
+    ("palegoldenrod", 0xEEE8AA),
+    ("palegreen", 0x98FB98),
+    ("paleturquoise", 0xAFEEEE),
+    ("palevioletred", 0xDB7093),
+    ("papayawhip", 0xFFEFD5),
+    ("peachpuff", 0xFFDAB9),
+    ("peru", 0xCD853F),
+    ("pink", 0xFFC0CB),
+    ("plum", 0xDDA0DD),
+    ("powderblue", 0xB0E0E6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xFF0000),
+    ("rosybrown", 0xBC8F8F),
+    ("royalblue", 0x4169E1),
+    ("saddlebrown", 0x8B4513),
+    ("salmon", 0xFA8072),
+    ("sandybrown", 0xF4A460),
+    ("seagreen", 0x2E8B57),
+    ("seashell", 0xFFF5EE),
+    ("sienna", 0xA0522D),
+    ("silver", 0xC0C0C0),
+    ("skyblue", 0x87CEEB),
+    ("slateblue", 0x6A5ACD),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xFFFAFA),
+    ("springgreen", 0x00FF7F),
+    ("steelblue", 0x4682B4),
+    ("tan", 0xD2B48C),
+    ("teal", 0x008080),
+    ("thistle", 0xD8BFD8),
+    ("tomato", 0xFF6347),
+    ("turquoise", 0x40E0D0),
+    ("violet", 0xEE82EE),
+    ("wheat", 0xF5DEB3),
+    ("white", 0xFFFFFF),
+    ("whitesmoke", 0xF5F5F5),
+    ("yellow", 0xFFFF00),
+    ("yellowgreen", 0x9ACD32),
+];
+
+/// Parses `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`, `rgb()`, `rgba()`, `hsl()`, `hsla()`,
+/// a CSS color name or a `$name` from the palette.
+pub fn parse_color(text: &str, palette: &Palette) -> Result<Color, String> {
+    let text = text.trim();
+    let lower = text.to_lowercase();
+    if let Some(name) = text.strip_prefix('$') {
+        return palette
+            .get(name)
+            .copied()
+            .ok_or_else(|| format!("Unknown palette color {}", text));
+    }
+    if let Some((function, args)) = lower.strip_suffix(')').and_then(|t| t.split_once('(')) {
+        return parse_function(function, args).map_err(|e| format!("{} in {}", e, text));
+    }
+    if lower == "transparent" {
+        return Ok(Color::RGBA(0, 0, 0, 0));
+    }
+    if let Some((_, rgb)) = NAMED.iter().find(|(name, _)| *name == lower) {
+        return Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8));
+    }
+    string_to_hex_color(text)
+}
+
+fn string_to_hex_color(color: &str) -> Result<Color, String> {
+    let nohash = color.strip_prefix('#').unwrap_or(color);
+    let digits: Vec<u8> = match nohash.len() {
+        // #rgb stands for #rrggbb
+        3 | 4 => nohash.bytes().flat_map(|d| [d, d]).collect(),
+        6 | 8 => nohash.bytes().collect(),
+        n => {
+            return Err(format!(
+                "Expected 3, 4, 6 or 8 chars but got {} in {}",
+                n, nohash
+            ))
+        }
+    };
+    let channels: Result<Vec<u8>, _> = digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(&String::from_utf8_lossy(pair), 16))
+        .collect();
+    match channels.map_err(|e| format!("Unable to decode {}, error {}", color, e))?[..] {
+        [r, g, b] => Ok(Color::RGB(r, g, b)),
+        [r, g, b, a] => Ok(Color::RGBA(r, g, b, a)),
+        _ => unreachable!(),
+    }
+}
+
+/// `rgb(255, 0, 0)`, `rgb(100% 0% 0% / 50%)`, `hsl(120, 50%, 25%)` and the like.
+fn parse_function(function: &str, args: &str) -> Result<Color, String> {
+    let args: Vec<&str> = args
+        .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|a| !a.is_empty())
+        .collect();
+    let alpha = match args.get(3) {
+        Some(a) => fraction(a, 1.0)?,
+        None => 1.0,
+    };
+    if args.len() < 3 || args.len() > 4 {
+        return Err(format!("Expected 3 or 4 values but got {}", args.len()));
+    }
+    let (r, g, b) = match function {
+        "rgb" | "rgba" => (
+            fraction(args[0], 255.0)?,
+            fraction(args[1], 255.0)?,
+            fraction(args[2], 255.0)?,
+        ),
+        "hsl" | "hsla" => {
+            let hue = args[0].trim_end_matches("deg");
+            let hue = hue
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid hue {}", args[0]))?;
+            hsl_to_rgb(
+                hue.rem_euclid(360.0),
+                fraction(args[1], 100.0)?,
+                fraction(args[2], 100.0)?,
+            )
+        }
+        _ => return Err(format!("Unknown color function {}", function)),
+    };
+    let channel = |v: f64| (v * 255.0).round() as u8;
+    Ok(Color::RGBA(
+        channel(r),
+        channel(g),
+        channel(b),
+        channel(alpha),
+    ))
+}
+
+/// A percentage, or a number up to `max`, as a fraction between 0 and 1.
+fn fraction(value: &str, max: f64) -> Result<f64, String> {
+    let (number, max) = match value.strip_suffix('%') {
+        Some(percent) => (percent, 100.0),
+        None => (value, max),
+    };
+    match number.parse::<f64>() {
+        Ok(n) if (0.0..=max).contains(&n) => Ok(n / max),
+        _ => Err(format!("Invalid value {}", value)),
+    }
+}
+
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> (f64, f64, f64) {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    (r + m, g + m, b + m)
+}
+
+/// `#RRGGBB`, or `#RRGGBBAA` for a translucent color, as read by `parse_color`.
+pub fn color_to_string(color: Color) -> String {
+    let rgb = format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b);
+    match color.a {
+        0xFF => rgb,
+        a => format!("{}{:02X}", rgb, a),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn str_hex(s: &str) -> Color {
+        parse_color(s, &Palette::new()).unwrap()
+    }
+
+    #[test]
+    fn string_to_hex_ok_test() {
+        assert_eq!(Color::RGB(90, 97, 122), str_hex("5a617a"));
+        assert_eq!(Color::RGB(90, 97, 122), str_hex("#5a617a"));
+        assert_eq!(Color::RGB(13, 28, 40), str_hex("0d1c28"));
+        assert_eq!(Color::RGB(13, 28, 40), str_hex("0D1C28"));
+        assert_eq!(Color::RGB(0, 0, 0), str_hex("000000"));
+        assert_eq!(Color::RGB(255, 255, 255), str_hex("FFFFFF"));
+        assert_eq!(Color::RGB(255, 170, 0), str_hex("#fa0"));
+        assert_eq!(Color::RGBA(255, 170, 0, 0x88), str_hex("#fa08"));
+        assert_eq!(Color::RGBA(90, 97, 122, 128), str_hex("#5a617a80"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unable to decode 5G617a, error invalid digit found")]
+    fn string_to_hex_panic1_test() {
+        assert_eq!(Color::RGB(90, 97, 122), str_hex("5G617a"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected 3, 4, 6 or 8 chars but got 9 in 123456789")]
+    fn string_to_hex_panic2_test() {
+        assert_eq!(Color::RGB(69, 103, 137), str_hex("123456789"));
+    }
+
+    #[test]
+    fn hex_to_string_ok_test() {
+        assert_eq!("#5A617A", color_to_string(Color::RGB(90, 97, 122)));
+        assert_eq!("#FFFFFF", color_to_string(Color::RGB(255, 255, 255)));
+        assert_eq!("#0D1C2880", color_to_string(Color::RGBA(13, 28, 40, 128)));
+        for text in &["#5A617A", "#0D1C2880", "#000000"] {
+            assert_eq!(*text, color_to_string(str_hex(text)));
+        }
+    }
+
+    #[test]
+    fn functions_test() {
+        assert_eq!(Color::RGB(255, 0, 0), str_hex("rgb(255, 0, 0)"));
+        assert_eq!(
+            Color::RGBA(255, 0, 0, 128),
+            str_hex("rgba(100%, 0%, 0%, 0.5)")
+        );
+        assert_eq!(Color::RGBA(0, 0, 255, 64), str_hex("rgb(0 0 255 / 25%)"));
+        assert_eq!(Color::RGB(32, 96, 32), str_hex("hsl(120, 50%, 25%)"));
+        assert_eq!(Color::RGB(255, 0, 0), str_hex("hsl(360deg 100% 50%)"));
+        assert!(parse_color("rgb(256, 0, 0)", &Palette::new()).is_err());
+        assert!(parse_color("rgb(1, 2)", &Palette::new()).is_err());
+        assert!(parse_color("cmyk(1, 2, 3, 4)", &Palette::new()).is_err());
+    }
+
+    #[test]
+    fn names_test() {
+        let mut palette = Palette::new();
+        palette.insert(String::from("accent"), Color::RGB(1, 2, 3));
+        assert_eq!(
+            Color::RGB(1, 2, 3),
+            parse_color("$accent", &palette).unwrap()
+        );
+        assert!(parse_color("$accnt", &palette).is_err());
+        assert_eq!(Color::RGB(0x66, 0x33, 0x99), str_hex("RebeccaPurple"));
+        assert_eq!(Color::RGBA(0, 0, 0, 0), str_hex("transparent"));
+        assert!(parse_color("notacolor", &palette).is_err());
+    }
+}
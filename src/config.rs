@@ -1,6 +1,17 @@
 use sdl2::pixels::Color;
 
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::assets;
+use crate::color::{parse_color, Palette};
+use crate::file_assist;
+use crate::format::COMMENT_LEADERS;
+use crate::highlight::{Attrs, Group, Highlights, Style, GROUPS};
+use crate::keymap::KeymapConfig;
 
 const BRIGHT_GRAY: Color = Color::RGB(44, 50, 60);
 const WHITE: Color = Color::RGB(255, 255, 255);
@@ -9,143 +20,719 @@ const BROWN: Color = Color::RGB(205, 133, 63);
 const DIM_GRAY: Color = Color::RGB(105, 105, 105);
 const SLATE: Color = Color::RGB(47, 79, 79);
 
-#[derive(Deserialize)]
-struct ColorSchemeJson {
-    pub buffer_fg: String,
-    pub buffer_bg: String,
-    pub cursor: String,
-    pub statusline_fg: String,
-    pub statusline_bg: String,
-    pub filepicker_fg: String,
-    pub filepicker_bg: String,
-    pub filepicker_border: String,
-    pub filepicker_selection: String,
-    pub itempicker_fg: String,
-    pub itempicker_bg: String,
-    pub itempicker_border: String,
-    pub itempicker_selection: String,
+/// Keys of a color scheme besides the flat fields.
+const SCHEME_KEYS: &[&str] = &["extends", "groups", "palette"];
+
+/// Groups whose colors stand for a scheme in the `:colorscheme` picker.
+const SWATCH: &[(&str, bool)] = &[
+    ("Normal", true),
+    ("Normal", false),
+    ("Comment", false),
+    ("Keyword", false),
+    ("String", false),
+    ("Function", false),
+    ("Type", false),
+    ("Constant", false),
+    ("Visual", true),
+    ("StatusLine", true),
+    ("Cursor", true),
+];
+
+const GROUP_KEYS: &[&str] = &["fg", "bg", "bold", "italic", "underline", "link"];
+
+/// Highlight group in a color scheme, either attributes or a `link` to another group.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct GroupJson {
+    fg: Option<String>,
+    bg: Option<String>,
+    bold: Option<bool>,
+    italic: Option<bool>,
+    underline: Option<bool>,
+    link: Option<String>,
+}
+
+/// Flat color scheme fields with the group they set and whether it is the background.
+const SCHEME_FIELDS: &[(&str, &str, bool)] = &[
+    ("buffer_fg", "Normal", false),
+    ("buffer_bg", "Normal", true),
+    ("cursor", "Cursor", true),
+    ("statusline_fg", "StatusLine", false),
+    ("statusline_bg", "StatusLine", true),
+    ("filepicker_fg", "FilePicker", false),
+    ("filepicker_bg", "FilePicker", true),
+    ("filepicker_border", "FilePicker.Border", false),
+    ("filepicker_selection", "FilePicker.Selection", true),
+    ("itempicker_fg", "ItemPicker", false),
+    ("itempicker_bg", "ItemPicker", true),
+    ("itempicker_border", "ItemPicker.Border", false),
+    ("itempicker_selection", "ItemPicker.Selection", true),
+];
+
+/// `comments` of filetypes by extension, before the config's `filetypes` sections.
+const FILETYPE_COMMENTS: &[(&[&str], &str)] = &[
+    (
+        &["rs", "c", "h", "cpp", "hpp", "java", "js", "ts", "go"],
+        "///,//!,//,*",
+    ),
+    (&["py", "sh", "toml", "yaml", "yml", "rb"], "#"),
+    (&["lua", "sql", "hs"], "--"),
+    (&["lisp", "el", "clj", "scm"], ";"),
+];
+
+/// Keymap profiles that can be selected with the `keymap` option.
+pub const KEYMAPS: &[&str] = &["default", "none"];
+
+/// Editor options changed with `:set`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Options {
+    pub textwidth: usize,
+    /// Keep undo history of written files across restarts.
+    pub undofile: bool,
+    /// Save the session when quitting, to restore it in the same directory. Off by default.
+    pub autosession: bool,
+    pub tabstop: usize,
+    /// Show line numbers.
+    pub number: bool,
+    /// Break long lines at the window edge instead of cutting them off.
+    pub wrap: bool,
+    /// Lines kept visible above and below the cursor.
+    pub scrolloff: usize,
+    /// Save the file when the window loses focus.
+    pub autosave: bool,
+    pub keymap: String,
+    /// Milliseconds to wait for the next key of a mapping.
+    pub timeoutlen: usize,
+    /// Comment leaders kept by `gq`, separated by commas.
+    pub comments: String,
 }
 
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            textwidth: 79,
+            undofile: false,
+            autosession: false,
+            tabstop: 4,
+            number: true,
+            wrap: false,
+            scrolloff: 0,
+            autosave: false,
+            keymap: String::from("default"),
+            timeoutlen: 1000,
+            comments: String::from(COMMENT_LEADERS),
+        }
+    }
+}
+
+impl Options {
+    /// Applies a `name=value` assignment, or `name`/`noname` for a flag, from `:set`.
+    pub fn set(&mut self, assignment: &str) -> Result<(), String> {
+        let assignment = assignment.trim();
+        let (flag, on) = match assignment.strip_prefix("no") {
+            Some(flag) => (flag, false),
+            None => (assignment, true),
+        };
+        let flag = match flag {
+            "undofile" | "udf" => Some(&mut self.undofile),
+            "autosession" => Some(&mut self.autosession),
+            "number" | "nu" => Some(&mut self.number),
+            "wrap" => Some(&mut self.wrap),
+            "autosave" => Some(&mut self.autosave),
+            _ => None,
+        };
+        if let Some(flag) = flag {
+            *flag = on;
+            return Ok(());
+        }
+        let (name, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| format!("Expected name=value but got {}", assignment))?;
+        let number = || {
+            value
+                .trim()
+                .parse::<usize>()
+                .map_err(|e| format!("Invalid value for {}: {}", name, e))
+        };
+        match name.trim() {
+            "textwidth" | "tw" => self.textwidth = number()?,
+            "tabstop" | "ts" => match number()? {
+                0 => return Err(String::from("tabstop must be positive")),
+                n => self.tabstop = n,
+            },
+            "scrolloff" | "so" => self.scrolloff = number()?,
+            "timeoutlen" | "tm" => self.timeoutlen = number()?,
+            "comments" | "com" => self.comments = value.trim().to_string(),
+            "keymap" | "km" => match value.trim() {
+                keymap if KEYMAPS.contains(&keymap) => self.keymap = keymap.to_string(),
+                keymap => return Err(format!("Unknown keymap profile: {}", keymap)),
+            },
+            other => return Err(format!("Unknown option: {}", other)),
+        }
+        Ok(())
+    }
+
+    /// Applies the options of a config section, returning the invalid ones.
+    fn apply(&mut self, section: &Map<String, Value>) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (name, value) in section {
+            let assignment = match value {
+                Value::Bool(true) => name.clone(),
+                Value::Bool(false) => format!("no{}", name),
+                Value::Number(n) => format!("{}={}", name, n),
+                Value::String(s) => format!("{}={}", name, s),
+                _ => {
+                    errors.push(format!("Invalid value for {}: {}", name, value));
+                    continue;
+                }
+            };
+            if let Err(msg) = self.set(&assignment) {
+                errors.push(msg);
+            }
+        }
+        errors
+    }
+}
+
+/// Top level keys of `config.json`.
+const CONFIG_KEYS: &[&str] = &[
+    "font",
+    "font_size",
+    "color_scheme",
+    "window_size",
+    "options",
+    "filetypes",
+    "keymaps",
+];
+
+/// Contents of `config.json`.
+#[derive(Debug)]
+pub struct Config {
+    /// Path of a TTF file or a font family, the built-in font when missing.
+    pub font: Option<String>,
+    pub font_size: u16,
+    pub color_scheme: Option<String>,
+    pub window_size: (u32, u32),
+    /// Options as for `:set`, e.g. `"tabstop": 8` or `"wrap": true`.
+    pub options: Map<String, Value>,
+    /// Options for files with an extension, e.g. `"rs": {"textwidth": 99}`.
+    pub filetypes: BTreeMap<String, Map<String, Value>>,
+    pub keymaps: KeymapConfig,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            font: None,
+            font_size: 13,
+            color_scheme: None,
+            window_size: (800, 600),
+            options: Map::new(),
+            filetypes: BTreeMap::new(),
+            keymaps: KeymapConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Parses a config, returning it along with the invalid options it sets.
+    /// Unknown keys are reported, invalid values reported and replaced by the defaults.
+    pub fn parse(json: &str) -> Result<(Config, Vec<String>), String> {
+        let fields: Map<String, Value> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+        for (key, value) in fields {
+            let result = match key.as_str() {
+                "font" => set_field(&mut config.font, value),
+                "font_size" => set_field(&mut config.font_size, value),
+                "color_scheme" => set_field(&mut config.color_scheme, value),
+                "window_size" => set_field(&mut config.window_size, value),
+                "options" => set_field(&mut config.options, value),
+                "filetypes" => set_field(&mut config.filetypes, value),
+                "keymaps" => set_field(&mut config.keymaps, value),
+                _ => {
+                    errors.push(format!(
+                        "Unknown field {}{}",
+                        key,
+                        suggest(&key, CONFIG_KEYS)
+                    ));
+                    continue;
+                }
+            };
+            if let Err(e) = result {
+                errors.push(format!("{}: {}", key, e));
+            }
+        }
+        errors.extend(Options::default().apply(&config.options));
+        for (filetype, section) in &config.filetypes {
+            let invalid = Options::default().apply(section);
+            errors.extend(invalid.into_iter().map(|e| format!("{} ({})", e, filetype)));
+        }
+        if config.font_size == 0 {
+            errors.push(String::from("font_size must be positive"));
+            config.font_size = Config::default().font_size;
+        }
+        if config.window_size.0 == 0 || config.window_size.1 == 0 {
+            errors.push(String::from("window_size must be positive"));
+            config.window_size = Config::default().window_size;
+        }
+        Ok((config, errors))
+    }
+
+    /// Reads the config, with one message listing everything wrong with it.
+    pub fn read_from_file(path: &Path) -> Result<(Config, Option<String>), String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        let (config, errors) = Config::parse(&json)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        let message = match errors.is_empty() {
+            true => None,
+            false => Some(format!("{}: {}", path.display(), errors.join(", "))),
+        };
+        Ok((config, message))
+    }
+
+    /// Options for `file`: the defaults, then the config, then its filetype section.
+    pub fn options_for(&self, file: &str) -> Options {
+        let mut options = Options::default();
+        let extension = Path::new(file).extension().map(|e| e.to_string_lossy());
+        let comments = FILETYPE_COMMENTS.iter().find(|(extensions, _)| {
+            extension
+                .as_ref()
+                .is_some_and(|e| extensions.contains(&e.as_ref()))
+        });
+        if let Some((_, comments)) = comments {
+            options.comments = comments.to_string();
+        }
+        options.apply(&self.options);
+        if let Some(section) = extension.and_then(|e| self.filetypes.get(e.as_ref())) {
+            options.apply(section);
+        }
+        options
+    }
+}
+
+/// `--config` if given, otherwise `config.json` in the config directory if it exists.
+pub fn config_path(explicit: Option<&str>) -> Option<PathBuf> {
+    match explicit {
+        Some(path) => Some(PathBuf::from(path)),
+        None => file_assist::config_dir()
+            .map(|dir| dir.join("config.json"))
+            .filter(|path| path.is_file()),
+    }
+}
+
+/// Colors of the editor as highlight groups.
+#[derive(Clone, PartialEq, Debug)]
 pub struct ColorScheme {
-    pub buffer_fg: Color,
-    pub buffer_bg: Color,
-    pub cursor: Color,
-    pub statusline_fg: Color,
-    pub statusline_bg: Color,
-    pub filepicker_fg: Color,
-    pub filepicker_bg: Color,
-    pub filepicker_border: Color,
-    pub filepicker_selection: Color,
-    pub itempicker_fg: Color,
-    pub itempicker_bg: Color,
-    pub itempicker_border: Color,
-    pub itempicker_selection: Color,
-}
-
-pub const DEFAULT_CS: ColorScheme = ColorScheme {
-    buffer_fg: WHITE,
-    buffer_bg: BRIGHT_GRAY,
-    cursor: BLUE,
-    statusline_fg: WHITE,
-    statusline_bg: BROWN,
-    filepicker_fg: WHITE,
-    filepicker_bg: DIM_GRAY,
-    filepicker_border: SLATE,
-    filepicker_selection: SLATE,
-    itempicker_fg: WHITE,
-    itempicker_bg: DIM_GRAY,
-    itempicker_border: SLATE,
-    itempicker_selection: SLATE,
-};
+    pub highlights: Highlights,
+    /// Named colors, including those of the schemes it extends.
+    pub palette: Palette,
+}
+
+impl Default for ColorScheme {
+    fn default() -> ColorScheme {
+        let mut highlights = Highlights::new(WHITE, BRIGHT_GRAY);
+        highlights.update("Cursor", |a| a.bg = Some(BLUE));
+        highlights.update("StatusLine", |a| a.bg = Some(BROWN));
+        highlights.update("FilePicker", |a| a.bg = Some(DIM_GRAY));
+        highlights.update("FilePicker.Border", |a| a.fg = Some(SLATE));
+        highlights.update("FilePicker.Selection", |a| a.bg = Some(SLATE));
+        ColorScheme {
+            highlights,
+            palette: Palette::new(),
+        }
+    }
+}
 
 impl ColorScheme {
-    pub fn read_from_file(file_path: &String) -> Result<ColorScheme, String> {
-        let file = std::fs::read_to_string(file_path).map_err(|e| e.to_string())?;
-        let cs_json = serde_json::from_str::<ColorSchemeJson>(&file).map_err(|e| e.to_string())?;
-        ColorScheme::json_to_cs(&cs_json)
-    }
-
-    fn json_to_cs(csj: &ColorSchemeJson) -> Result<ColorScheme, String> {
-        Ok(ColorScheme {
-            buffer_fg: string_to_hex_color(&csj.buffer_fg)?,
-            buffer_bg: string_to_hex_color(&csj.buffer_bg)?,
-            cursor: string_to_hex_color(&csj.cursor)?,
-            statusline_bg: string_to_hex_color(&csj.statusline_bg)?,
-            statusline_fg: string_to_hex_color(&csj.statusline_fg)?,
-            filepicker_fg: string_to_hex_color(&csj.filepicker_fg)?,
-            filepicker_bg: string_to_hex_color(&csj.filepicker_bg)?,
-            filepicker_border: string_to_hex_color(&csj.filepicker_border)?,
-            filepicker_selection: string_to_hex_color(&csj.filepicker_selection)?,
-            itempicker_fg: string_to_hex_color(&csj.itempicker_fg)?,
-            itempicker_bg: string_to_hex_color(&csj.itempicker_bg)?,
-            itempicker_border: string_to_hex_color(&csj.itempicker_border)?,
-            itempicker_selection: string_to_hex_color(&csj.itempicker_selection)?,
-        })
-    }
-}
-
-fn strip_hash(color: &str) -> &str {
-    if color.starts_with('#') {
-        &color[1..]
-    } else {
-        color
-    }
-}
-
-fn string_to_hex_color(color: &str) -> Result<Color, String> {
-    let nohash: &str = strip_hash(color);
-    if nohash.len() == 6 {
-        usize::from_str_radix(nohash, 16)
-            .map(|num| {
-                let r = (num >> 16) & 0xFF;
-                let g = (num >> 8) & 0xFF;
-                let b = num & 0xFF;
-                Color::RGB(r as u8, g as u8, b as u8)
+    /// Reads the scheme and the ones it extends, with warnings about what was ignored.
+    pub fn read_from_file(file_path: &str) -> Result<(ColorScheme, Vec<String>), String> {
+        ColorScheme::read_with(file_path, &assets::theme_text)
+    }
+
+    fn read_with(name: &str, read: &Reader) -> Result<(ColorScheme, Vec<String>), String> {
+        let mut warnings = Vec::new();
+        let cs = ColorScheme::load(name, read, &mut Vec::new(), &mut warnings)?;
+        let links = cs.highlights.check_links();
+        warnings.extend(links.into_iter().map(|w| format!("{}: {}", name, w)));
+        Ok((cs, warnings))
+    }
+
+    fn load(
+        name: &str,
+        read: &Reader,
+        chain: &mut Vec<String>,
+        warnings: &mut Vec<String>,
+    ) -> Result<ColorScheme, String> {
+        let extends_itself = chain.iter().any(|n| n == name);
+        chain.push(name.to_string());
+        if extends_itself {
+            return Err(format!(
+                "Color scheme extends itself: {}",
+                chain.join(" -> ")
+            ));
+        }
+        let json = read(name)?;
+        let scheme: Map<String, Value> = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid color scheme {}: {}", name, e))?;
+        let mut cs = match scheme.get("extends") {
+            Some(Value::String(parent)) => ColorScheme::load(parent, read, chain, warnings)?,
+            Some(_) => return Err(format!("{}: extends must be a color scheme name", name)),
+            None => ColorScheme::default(),
+        };
+        let mut own = Vec::new();
+        cs.apply(&scheme, &mut own)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        warnings.extend(own.into_iter().map(|w| format!("{}: {}", name, w)));
+        Ok(cs)
+    }
+
+    /// Sets the palette, the fields and then the groups of a scheme over the current ones.
+    fn apply(
+        &mut self,
+        scheme: &Map<String, Value>,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), String> {
+        match scheme.get("palette") {
+            Some(Value::Object(palette)) => self.add_palette(palette)?,
+            Some(_) => return Err(String::from("palette must be an object")),
+            None => (),
+        }
+        let fields = SCHEME_FIELDS.iter().map(|(field, _, _)| *field);
+        let known: Vec<&str> = fields.chain(SCHEME_KEYS.iter().copied()).collect();
+        for (key, value) in scheme {
+            match SCHEME_FIELDS.iter().find(|(field, _, _)| field == key) {
+                Some((field, group, background)) => {
+                    let color = value
+                        .as_str()
+                        .ok_or_else(|| format!("Expected a color in {}", field))
+                        .and_then(|text| parse_color(text, &self.palette))?;
+                    self.highlights.update(group, |a| match background {
+                        true => a.bg = Some(color),
+                        false => a.fg = Some(color),
+                    });
+                }
+                None if known.contains(&key.as_str()) => (),
+                None => warnings.push(format!("Unknown field {}{}", key, suggest(key, &known))),
+            }
+        }
+        let groups = match scheme.get("groups") {
+            Some(Value::Object(groups)) => groups.clone(),
+            Some(_) => return Err(String::from("groups must be an object")),
+            None => Map::new(),
+        };
+        for (name, attrs) in groups {
+            let root = name.split('.').next().unwrap_or_default();
+            if !GROUPS.contains(&root) {
+                warnings.push(format!("Unknown group {}{}", name, suggest(root, GROUPS)));
+            }
+            let mut attrs = match attrs {
+                Value::Object(attrs) => attrs,
+                _ => return Err(format!("Group {} must be an object", name)),
+            };
+            attrs.retain(|key, _| {
+                let known = GROUP_KEYS.contains(&key.as_str());
+                if !known {
+                    let hint = suggest(key, GROUP_KEYS);
+                    warnings.push(format!(
+                        "Unknown attribute {} in group {}{}",
+                        key, name, hint
+                    ));
+                }
+                known
+            });
+            let group = serde_json::from_value::<GroupJson>(Value::Object(attrs))
+                .map_err(|e| e.to_string())
+                .and_then(|group| json_to_group(&group, &self.palette))
+                .map_err(|e| format!("{} in group {}", e, name))?;
+            self.highlights.set(&name, group);
+        }
+        Ok(())
+    }
+
+    /// Adds the colors of a `palette`, which may refer to each other by `$name`.
+    fn add_palette(&mut self, palette: &Map<String, Value>) -> Result<(), String> {
+        let mut pending: Vec<(&String, &Value)> = palette.iter().collect();
+        loop {
+            let count = pending.len();
+            let mut error = None;
+            pending.retain(|(name, value)| {
+                let color = match value.as_str() {
+                    Some(text) => parse_color(text, &self.palette),
+                    None => Err(format!("Expected a color in palette {}", name)),
+                };
+                match color {
+                    Ok(color) => {
+                        self.palette.insert(name.to_string(), color);
+                        false
+                    }
+                    Err(e) => {
+                        error = Some(format!("{} in palette {}", e, name));
+                        true
+                    }
+                }
+            });
+            match error {
+                Some(e) if pending.len() == count => return Err(e),
+                Some(_) => (),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    pub fn style(&self, group: &str) -> Style {
+        self.highlights.style(group)
+    }
+
+    /// Background and main colors of the scheme, each a background or foreground of a group.
+    pub fn swatch(&self) -> Vec<Color> {
+        SWATCH
+            .iter()
+            .map(|(group, background)| match background {
+                true => self.style(group).bg,
+                false => self.style(group).fg,
             })
-            .map_err(|e| format!("Unable to decode {}, error {}", color, e))
-    } else {
-        Err(format!(
-            "Expected 6 chars but got {} in {}",
-            nohash.len(),
-            nohash
-        ))
+            .collect()
     }
 }
 
-fn hex_to_string_color(color: &Color) -> String {
-    format!("{:X}", (color.r << 16) + (color.g << 8) + color.b)
+/// Replaces `field` with `value`, keeping it when `value` doesn't fit.
+fn set_field<T: DeserializeOwned>(field: &mut T, value: Value) -> Result<(), String> {
+    *field = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Reads a color scheme given by name or path.
+type Reader = dyn Fn(&str) -> Result<String, String>;
+
+/// ` (did you mean ...?)` naming the candidate closest to a misspelled `word`.
+fn suggest(word: &str, candidates: &[&str]) -> String {
+    let word = word.to_lowercase();
+    candidates
+        .iter()
+        .map(|c| (edit_distance(&word, &c.to_lowercase()), c))
+        .filter(|(distance, _)| *distance <= usize::clamp(word.len().div_ceil(2), 1, 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map_or_else(String::new, |(_, c)| format!(" (did you mean {}?)", c))
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + (ca != *cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+fn json_to_group(group: &GroupJson, palette: &Palette) -> Result<Group, String> {
+    if let Some(target) = &group.link {
+        let attrs = [&group.fg, &group.bg];
+        let flags = [group.bold, group.italic, group.underline];
+        if attrs.iter().any(|a| a.is_some()) || flags.iter().any(|f| f.is_some()) {
+            return Err(String::from("Attributes can't be combined with link"));
+        }
+        return Ok(Group::Link(target.clone()));
+    }
+    let color = |c: &Option<String>| {
+        c.as_deref()
+            .map(|text| parse_color(text, palette))
+            .transpose()
+    };
+    Ok(Group::Attrs(Attrs {
+        fg: color(&group.fg)?,
+        bg: color(&group.bg)?,
+        bold: group.bold,
+        italic: group.italic,
+        underline: group.underline,
+    }))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn str_hex(s: &str) -> super::Color {
-        string_to_hex_color(s).unwrap()
+    fn str_hex(s: &str) -> Color {
+        parse_color(s, &Palette::new()).unwrap()
+    }
+
+    fn schemes(name: &str) -> Result<String, String> {
+        let json = match name {
+            "base" => {
+                r##"{"buffer_bg": "#1B2B34", "cursor": "48cae4", "groups": {"Keyword": {"fg": "#C594C5"}}}"##
+            }
+            "child" => r#"{"extends": "base", "groups": {"Keyword": {"bold": true}}}"#,
+            "loop" => r#"{"extends": "loop"}"#,
+            "palette" => {
+                r##"{"extends": "base", "palette": {"accent": "$blue", "blue": "hsl(210, 50%, 60%)"}, "groups": {"Keyword": {"fg": "$accent"}, "Visual": {"bg": "#4F5B6680"}}}"##
+            }
+            "typos" => r#"{"bufer_fg": "000000", "groups": {"Keywrd": {"bodl": true}}}"#,
+            "links" => {
+                r#"{"groups": {"Number": {"link": "Nope"}, "Comment": {"link": "LineNr"}, "LineNr": {"link": "Comment"}}}"#
+            }
+            _ => return Err(format!("Color scheme not found: {}", name)),
+        };
+        Ok(json.to_string())
+    }
+
+    #[test]
+    fn color_scheme_test() {
+        let json = r##"{
+            "buffer_bg": "#1B2B34",
+            "cursor": "48cae4",
+            "groups": {
+                "Normal": {"fg": "#CDCFC4"},
+                "Keyword": {"fg": "#C594C5", "bold": true},
+                "LineNr": {"link": "Comment"},
+                "Comment": {"fg": "#65737E", "italic": true}
+            }
+        }"##;
+        let read = move |_: &str| Ok(json.to_string());
+        let (cs, warnings) = ColorScheme::read_with("scheme", &read).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(str_hex("CDCFC4"), cs.style("Normal").fg);
+        assert_eq!(str_hex("1B2B34"), cs.style("Keyword").bg);
+        assert_eq!(str_hex("48cae4"), cs.style("Cursor").bg);
+        assert!(cs.style("Keyword").bold);
+        assert_eq!(cs.style("Comment"), cs.style("LineNr"));
+        assert_eq!(str_hex("CDCFC4"), cs.style("StatusLine").fg);
+        let swatch = cs.swatch();
+        assert_eq!(SWATCH.len(), swatch.len());
+        assert_eq!(
+            (str_hex("1B2B34"), str_hex("C594C5")),
+            (swatch[0], swatch[3])
+        );
+
+        let link = r#"{"groups": {"LineNr": {"link": "Comment", "bold": true}}}"#;
+        assert!(ColorScheme::read_with("scheme", &move |_| Ok(link.to_string())).is_err());
+        let field = r#"{"buffer_fg": "12345"}"#;
+        assert!(ColorScheme::read_with("scheme", &move |_| Ok(field.to_string())).is_err());
+    }
+
+    #[test]
+    fn extends_test() {
+        let (cs, warnings) = ColorScheme::read_with("child", &schemes).unwrap();
+        assert!(warnings.is_empty());
+        let keyword = cs.style("Keyword");
+        assert_eq!((str_hex("C594C5"), true), (keyword.fg, keyword.bold));
+        assert_eq!(str_hex("48cae4"), cs.style("Cursor").bg);
+        assert_eq!(DIM_GRAY, cs.style("FilePicker").bg);
+        assert_eq!(
+            Err(String::from("Color scheme extends itself: loop -> loop")),
+            ColorScheme::read_with("loop", &schemes)
+        );
+    }
+
+    #[test]
+    fn palette_test() {
+        let (cs, _) = ColorScheme::read_with("palette", &schemes).unwrap();
+        assert_eq!(Color::RGB(102, 153, 204), cs.style("Keyword").fg);
+        assert_eq!(Color::RGBA(0x4F, 0x5B, 0x66, 0x80), cs.style("Visual").bg);
+        assert_eq!(str_hex("48cae4"), cs.style("Cursor").bg);
+        let json = r#"{"palette": {"a": "$b", "b": "$a"}}"#;
+        let read = move |_: &str| Ok(json.to_string());
+        assert!(ColorScheme::read_with("scheme", &read).is_err());
     }
 
     #[test]
-    fn string_to_hex_ok_test() {
-        assert_eq!(super::Color::RGB(90, 97, 122), str_hex("5a617a"));
-        assert_eq!(super::Color::RGB(90, 97, 122), str_hex("#5a617a"));
-        assert_eq!(super::Color::RGB(13, 28, 40), str_hex("0d1c28"));
-        assert_eq!(super::Color::RGB(13, 28, 40), str_hex("0D1C28"));
-        assert_eq!(super::Color::RGB(0, 0, 0), str_hex("000000"));
-        assert_eq!(super::Color::RGB(255, 255, 255), str_hex("FFFFFF"));
+    fn validate_test() {
+        let (_, warnings) = ColorScheme::read_with("typos", &schemes).unwrap();
+        assert_eq!(
+            vec![
+                "typos: Unknown field bufer_fg (did you mean buffer_fg?)",
+                "typos: Unknown group Keywrd (did you mean Keyword?)",
+                "typos: Unknown attribute bodl in group Keywrd (did you mean bold?)",
+            ],
+            warnings
+        );
+        let (_, warnings) = ColorScheme::read_with("links", &schemes).unwrap();
+        assert_eq!(
+            vec![
+                "links: Link cycle: Comment -> LineNr -> Comment",
+                "links: Group Number links to unknown group Nope",
+            ],
+            warnings
+        );
+        for theme in assets::list_themes() {
+            assert_eq!(
+                Ok(vec![]),
+                ColorScheme::read_from_file(&theme).map(|(_, w)| w)
+            );
+        }
     }
 
     #[test]
-    #[should_panic(expected = "Unable to decode 5G617a, error invalid digit found")]
-    fn string_to_hex_panic1_test() {
-        assert_eq!(super::Color::RGB(90, 97, 122), str_hex("5G617a"));
+    fn options_set_test() {
+        let mut options = Options::default();
+        assert_eq!(Ok(()), options.set("tw=40"));
+        assert_eq!(40, options.textwidth);
+        assert!(options.set("textwidth=abc").is_err());
+        assert!(options.set("nosuchoption=1").is_err());
+        assert!(options.set("textwidth").is_err());
+        assert_eq!(Ok(()), options.set("undofile"));
+        assert!(options.undofile);
+        assert!(!options.autosession);
+        assert_eq!(Ok(()), options.set("autosession"));
+        assert!(options.autosession);
+        assert_eq!(Ok(()), options.set("com=#,--"));
+        assert_eq!("#,--", options.comments);
+        assert_eq!(Ok(()), options.set("ts=8"));
+        assert_eq!(8, options.tabstop);
+        assert!(options.set("ts=0").is_err());
+        assert!(options.set("keymap=emacs").is_err());
     }
 
     #[test]
-    #[should_panic(expected = "Expected 6 chars but got 9 in 123456789")]
-    fn string_to_hex_panic2_test() {
-        assert_eq!(super::Color::RGB(69, 103, 137), str_hex("123456789"));
+    fn config_test() {
+        let json = r#"{
+            "font_size": 15,
+            "options": {"tabstop": 8, "wrap": true},
+            "filetypes": {"md": {"textwidth": 72, "nowrap": 1, "wrap": false}}
+        }"#;
+        let (config, errors) = Config::parse(json).unwrap();
+        assert_eq!(15, config.font_size);
+        assert_eq!((800, 600), config.window_size);
+        assert_eq!(vec![String::from("Unknown option: nowrap (md)")], errors);
+        let options = config.options_for("src/main.rs");
+        assert_eq!("///,//!,//,*", options.comments);
+        assert_eq!(
+            (8, true, 79),
+            (options.tabstop, options.wrap, options.textwidth)
+        );
+        let options = config.options_for("README.md");
+        assert_eq!(
+            (8, false, 72),
+            (options.tabstop, options.wrap, options.textwidth)
+        );
+        assert!(Config::parse("[]").is_err());
     }
 
     #[test]
-    fn hex_to_string_ok_test() {}
+    fn invalid_fields_test() {
+        let json = r#"{"fontsize": 15, "font_size": "big", "window_size": [640, 480]}"#;
+        let (config, errors) = Config::parse(json).unwrap();
+        assert_eq!((13, (640, 480)), (config.font_size, config.window_size));
+        assert_eq!(
+            vec![
+                "font_size: invalid type: string \"big\", expected u16",
+                "Unknown field fontsize (did you mean font_size?)",
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn invalid_size_test() {
+        let json = r#"{"font_size": 0, "window_size": [0, 400]}"#;
+        let (config, errors) = Config::parse(json).unwrap();
+        assert_eq!((13, (800, 600)), (config.font_size, config.window_size));
+        assert_eq!(2, errors.len());
+    }
 }
@@ -0,0 +1,142 @@
+/// Code points of bytes 0x80..=0x9f in Windows-1252, the rest match Latin-1. The
+/// five unassigned bytes map to the control characters with the same value.
+const CP1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Windows-1252, also used for Latin-1 files.
+    Cp1252,
+}
+
+impl Encoding {
+    /// Parses a `fileencoding` value.
+    pub fn from_name(name: &str) -> Result<Encoding, String> {
+        match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16" | "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            "cp1252" | "windows-1252" | "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Cp1252),
+            other => Err(format!("Unsupported fileencoding: {}", other)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Cp1252 => "cp1252",
+        }
+    }
+
+    /// UTF-16 is only recognized by its BOM, anything that isn't valid UTF-8 is
+    /// read as Windows-1252.
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        if bytes.starts_with(&[0xff, 0xfe]) {
+            Encoding::Utf16Le
+        } else if bytes.starts_with(&[0xfe, 0xff]) {
+            Encoding::Utf16Be
+        } else if std::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else {
+            Encoding::Cp1252
+        }
+    }
+
+    /// Decodes `bytes`, a BOM is kept as the first character. Invalid input is
+    /// replaced with U+FFFD and reported as an error along with the text.
+    pub fn decode(&self, bytes: &[u8]) -> Result<String, (String, String)> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec()).map_err(|e| {
+                let text = String::from_utf8_lossy(e.as_bytes()).to_string();
+                let at = e.utf8_error().valid_up_to();
+                (text, format!("invalid UTF-8 at byte {}", at))
+            }),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units: Vec<u16> = bytes
+                    .chunks(2)
+                    .map(|pair| {
+                        let pair = [pair[0], *pair.get(1).unwrap_or(&0)];
+                        if *self == Encoding::Utf16Le {
+                            u16::from_le_bytes(pair)
+                        } else {
+                            u16::from_be_bytes(pair)
+                        }
+                    })
+                    .collect();
+                match String::from_utf16(&units) {
+                    Ok(text) if bytes.len().is_multiple_of(2) => Ok(text),
+                    _ => Err((
+                        String::from_utf16_lossy(&units),
+                        format!("invalid {}", self.name()),
+                    )),
+                }
+            }
+            Encoding::Cp1252 => Ok(bytes
+                .iter()
+                .map(|b| match b {
+                    0x80..=0x9f => CP1252_HIGH[*b as usize - 0x80],
+                    _ => *b as char,
+                })
+                .collect()),
+        }
+    }
+
+    /// Fails on the first character the encoding can't represent.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            Encoding::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Encoding::Cp1252 => text
+                .chars()
+                .map(|c| match CP1252_HIGH.iter().position(|h| *h == c) {
+                    Some(i) => Ok(0x80 + i as u8),
+                    None if (c as u32) < 0x80 || (0xa0..=0xff).contains(&(c as u32)) => Ok(c as u8),
+                    None => Err(format!("Cannot convert {:?} to {}", c, self.name())),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_test() {
+        assert_eq!(Encoding::Utf16Le, Encoding::detect(b"\xff\xfea\x00"));
+        assert_eq!(Encoding::Utf16Be, Encoding::detect(b"\xfe\xff\x00a"));
+        assert_eq!(Encoding::Utf8, Encoding::detect("zażółć".as_bytes()));
+        assert_eq!(Encoding::Cp1252, Encoding::detect(b"caf\xe9"));
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let cases: [(Encoding, &[u8], &str); 3] = [
+            (Encoding::Utf16Le, b"\xff\xfeh\x00\xe9\x00", "\u{feff}hé"),
+            (Encoding::Utf16Be, b"\xfe\xff\xd8\x3d\xde\x00", "\u{feff}😀"),
+            (Encoding::Cp1252, b"\x80 caf\xe9", "€ café"),
+        ];
+        for (encoding, bytes, text) in cases {
+            assert_eq!(Ok(text.to_string()), encoding.decode(bytes));
+            assert_eq!(Ok(bytes.to_vec()), encoding.encode(text));
+        }
+    }
+
+    #[test]
+    fn invalid_test() {
+        assert!(Encoding::Utf16Le.decode(b"\xff\xfea").is_err());
+        assert!(Encoding::Cp1252.encode("zażółć").is_err());
+        assert_eq!(Ok(Encoding::Cp1252), Encoding::from_name("Latin1"));
+    }
+}
@@ -0,0 +1,163 @@
+/// What line addresses in a command are resolved against, rows are 0-based.
+pub struct Context {
+    pub cursor_row: usize,
+    pub last_row: usize,
+    pub visual: Option<(usize, usize)>,
+}
+
+/// Command typed on the `:` command line.
+#[derive(PartialEq, Debug)]
+pub struct ExCommand {
+    /// First and last row, both inclusive.
+    pub range: Option<(usize, usize)>,
+    pub name: String,
+    pub bang: bool,
+    pub args: String,
+}
+
+/// Command names with a dash, which would otherwise end at it.
+const DASHED: &[&str] = &["theme-import"];
+
+/// Parses `[range]name[!] [args]`, where range is `%` or `addr[,addr]` and an
+/// address is a line number, `.`, `$`, `'<` or `'>` with optional `+N`/`-N`.
+pub fn parse(line: &str, ctx: &Context) -> Result<ExCommand, String> {
+    let line = line.trim_start();
+    let (range, rest) = if let Some(rest) = line.strip_prefix('%') {
+        (Some((0, ctx.last_row)), rest)
+    } else {
+        match address(line, ctx)? {
+            Some((first, rest)) => match rest.strip_prefix(',') {
+                Some(rest) => match address(rest, ctx)? {
+                    Some((second, rest)) => (Some((first, second)), rest),
+                    None => return Err(format!("Invalid range: {}", line)),
+                },
+                None => (Some((first, first)), rest),
+            },
+            None => (None, line),
+        }
+    };
+    let range = match range {
+        Some((a, b)) if a > ctx.last_row || b > ctx.last_row => {
+            return Err(format!("Range out of bounds: {}", line))
+        }
+        Some((a, b)) => Some((usize::min(a, b), usize::max(a, b))),
+        None => None,
+    };
+    let rest = rest.trim_start();
+    let dashed = DASHED.iter().find(|name| {
+        rest.strip_prefix(*name)
+            .is_some_and(|after| !after.starts_with(|c: char| c.is_ascii_alphabetic()))
+    });
+    let name_len = if rest.starts_with('!') {
+        1
+    } else if let Some(name) = dashed {
+        name.len()
+    } else {
+        rest.find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len())
+    };
+    let (name, rest) = rest.split_at(name_len);
+    let (bang, args) = match rest.strip_prefix('!') {
+        Some(args) if name != "!" => (true, args),
+        _ => (false, rest),
+    };
+    Ok(ExCommand {
+        range,
+        name: name.to_string(),
+        bang,
+        args: args.trim().to_string(),
+    })
+}
+
+fn address<'a>(text: &'a str, ctx: &Context) -> Result<Option<(usize, &'a str)>, String> {
+    let (base, mut rest) = if let Some(rest) = text.strip_prefix('.') {
+        (ctx.cursor_row as i64, rest)
+    } else if let Some(rest) = text.strip_prefix('$') {
+        (ctx.last_row as i64, rest)
+    } else if text.starts_with("'<") || text.starts_with("'>") {
+        let (start, end) = ctx
+            .visual
+            .ok_or_else(|| String::from("No visual selection"))?;
+        let row = if text.starts_with("'<") { start } else { end };
+        (row as i64, &text[2..])
+    } else {
+        let digits = text.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits > 0 {
+            let number: i64 = text[..digits].parse().map_err(|_| text.to_string())?;
+            (number - 1, &text[digits..])
+        } else if text.starts_with('+') || text.starts_with('-') {
+            (ctx.cursor_row as i64, text)
+        } else {
+            return Ok(None);
+        }
+    };
+    let mut row = base;
+    while let Some(sign) = rest.chars().next().filter(|c| *c == '+' || *c == '-') {
+        let digits = rest[1..].chars().take_while(|c| c.is_ascii_digit()).count();
+        let offset: i64 = if digits == 0 {
+            1
+        } else {
+            rest[1..=digits].parse().map_err(|_| rest.to_string())?
+        };
+        row += if sign == '+' { offset } else { -offset };
+        rest = &rest[1 + digits..];
+    }
+    if row < 0 {
+        return Err(format!("Invalid address: {}", text));
+    }
+    Ok(Some((row as usize, rest)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTX: Context = Context {
+        cursor_row: 4,
+        last_row: 9,
+        visual: Some((2, 3)),
+    };
+
+    fn command(range: Option<(usize, usize)>, name: &str, bang: bool, args: &str) -> ExCommand {
+        ExCommand {
+            range,
+            name: name.to_string(),
+            bang,
+            args: args.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(
+            Ok(command(Some((0, 9)), "sort", true, "n")),
+            parse("%sort! n", &CTX)
+        );
+        assert_eq!(
+            Ok(command(Some((2, 3)), "sort", false, "")),
+            parse("'<,'>sort", &CTX)
+        );
+        assert_eq!(
+            Ok(command(Some((4, 6)), "!", false, "jq .")),
+            parse(".,+2!jq .", &CTX)
+        );
+        assert_eq!(
+            Ok(command(Some((0, 9)), "!", false, "sort")),
+            parse("1,$!sort", &CTX)
+        );
+        assert_eq!(
+            Ok(command(None, "set", false, "tw=40")),
+            parse("set tw=40", &CTX)
+        );
+        assert_eq!(
+            Ok(command(None, "theme-import", false, "a.tmTheme")),
+            parse("theme-import a.tmTheme", &CTX)
+        );
+        assert_eq!(
+            Ok(command(None, "s", false, "-a-b-")),
+            parse("s-a-b-", &CTX)
+        );
+        assert_eq!(Ok(command(Some((3, 3)), "", false, "")), parse("4", &CTX));
+        assert!(parse("5,20sort", &CTX).is_err());
+    }
+}
@@ -1,45 +1,285 @@
-use std::fs::File;
-use std::fs::OpenOptions;
+use std::fs::{self, File, OpenOptions, Permissions};
 use std::io::prelude::*;
-use std::io::Read;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
-pub fn open_file(path_name: &str) -> String {
+use crate::encoding::Encoding;
+use crate::file_format::FileFormat;
+
+/// Contents of a file, or an empty text for a file that does not exist yet.
+#[derive(PartialEq, Debug)]
+pub struct OpenedFile {
+    pub text: String,
+    pub format: FileFormat,
+    pub read_only: bool,
+    /// Shown to the user, e.g. for new files or undecodable bytes.
+    pub message: Option<String>,
+}
+
+pub fn open_file(path_name: &str) -> Result<OpenedFile, String> {
     println!("Opening file: {}", path_name);
-    let mut file = File::open(path_name).unwrap();
+    let bytes = match fs::read(path_name) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Ok(OpenedFile {
+                text: String::new(),
+                format: FileFormat::default(),
+                read_only: false,
+                message: Some(format!("\"{}\" [New File]", path_name)),
+            })
+        }
+        Err(_) if Path::new(path_name).is_dir() => {
+            return Err(format!("\"{}\" is a directory", path_name))
+        }
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+            return Err(format!("\"{}\" Permission denied", path_name))
+        }
+        Err(e) => return Err(format!("Unable to open {}: {}", path_name, e)),
+    };
+    let writable = fs::metadata(path_name).is_ok_and(|m| !m.permissions().readonly());
+    Ok(decode(path_name, &bytes, writable))
+}
+
+/// Reads all of stdin, decoded like a file.
+pub fn read_stdin() -> Result<OpenedFile, String> {
+    let mut bytes = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Unable to read stdin: {}", e))?;
+    Ok(decode("[stdin]", &bytes, true))
+}
 
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).unwrap();
-    contents
+fn decode(path_name: &str, bytes: &[u8], writable: bool) -> OpenedFile {
+    let encoding = Encoding::detect(bytes);
+    let (text, invalid) = match encoding.decode(bytes) {
+        Ok(text) => (text, None),
+        Err((text, msg)) => (text, Some(msg)),
+    };
+    let (text, mut format) = FileFormat::decode(&text);
+    format.encoding = encoding;
+    let message = match &invalid {
+        // saving the replaced characters back would corrupt the file
+        Some(msg) => Some(format!("\"{}\" [readonly] {}", path_name, msg)),
+        None if !writable => Some(format!("\"{}\" [readonly]", path_name)),
+        None if encoding != Encoding::Utf8 => {
+            Some(format!("\"{}\" [{}]", path_name, encoding.name()))
+        }
+        None => None,
+    };
+    OpenedFile {
+        text,
+        format,
+        read_only: !writable || invalid.is_some(),
+        message,
+    }
 }
 
-pub fn save_file(path_name: &str, content: &Vec<String>) {
+/// Writes `content` to a temporary file next to `path_name`, syncs it and renames it
+/// over the original, so a failed save never leaves a truncated file behind.
+pub fn save_file(path_name: &str, content: &[String], format: &FileFormat) -> Result<(), String> {
     println!("Trying to save file {}", path_name);
-    let mut file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(path_name)
-        .unwrap();
-    for l in content {
-        file.write_all(l.as_bytes()).unwrap();
-        file.write(b"\n").unwrap();
+    let error = |e: std::io::Error| format!("Unable to save {}: {}", path_name, e);
+    // write through symlinks instead of replacing them
+    let path = fs::canonicalize(path_name).unwrap_or_else(|_| PathBuf::from(path_name));
+    let permissions = fs::metadata(&path).ok().map(|m| m.permissions());
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("Not a file: {}", path_name))?;
+    let tmp_path = dir.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let bytes = format.encoding.encode(&format.encode(content))?;
+    let written = write_synced(&tmp_path, &bytes, permissions);
+    if let Err(e) = written.and_then(|_| fs::rename(&tmp_path, &path)) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(error(e));
+    }
+    // persist the rename itself, not every platform allows syncing a directory
+    if let Ok(dir) = File::open(&dir) {
+        let _ = dir.sync_all();
     }
     println!("File saved {}", path_name);
+    Ok(())
+}
+
+fn write_synced(
+    path: &Path,
+    bytes: &[u8],
+    permissions: Option<Permissions>,
+) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+/// `diff -u` of the file at `path_name` against `lines`.
+pub fn diff(path_name: &str, lines: &[String]) -> Result<Vec<String>, String> {
+    let mut child = Command::new("diff")
+        .arg("-u")
+        .arg(path_name)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Unable to run diff: {}", e))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let text = FileFormat::default().encode(lines);
+    // written from a thread so a large diff filling stdout can't block us
+    let writer = std::thread::spawn(move || stdin.write_all(text.as_bytes()));
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    let _ = writer.join();
+    // diff exits with 1 when the files differ
+    match output.status.code() {
+        Some(0) | Some(1) => Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(String::from)
+            .collect()),
+        _ => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/rslush`, for the user's config and color schemes.
+pub fn config_dir() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".config")))?;
+    Some(config.join("rslush"))
 }
 
-pub fn list_color_schemes() -> Vec<String> {
-    list_files("./colors/", |e| e.ends_with(".json"))
+/// `$XDG_STATE_HOME/rslush`, for files that should survive a restart but aren't config.
+pub fn state_dir() -> Option<PathBuf> {
+    let state = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".local/state")))?;
+    Some(state.join("rslush"))
 }
 
-fn list_files<F: Fn(&str) -> bool>(directory: &str, filter: F) -> Vec<String> {
+/// File about `file` in the `kind` state subdirectory, named after its absolute path.
+pub fn state_file(kind: &str, file: &str, extension: &str) -> Option<PathBuf> {
+    let path = fs::canonicalize(file).unwrap_or_else(|_| PathBuf::from(file));
+    let flat = path.to_string_lossy().replace('/', "%");
+    Some(
+        state_dir()?
+            .join(kind)
+            .join(format!("{}.{}", flat, extension)),
+    )
+}
+
+/// Names of the files in `directory` accepted by `filter`.
+pub fn list_files<F: Fn(&str) -> bool>(directory: &Path, filter: F) -> Vec<String> {
     match std::fs::read_dir(directory) {
         Ok(dir_entry) => {
             let c: Vec<std::fs::DirEntry> = dir_entry.filter_map(|e| e.ok()).collect();
             c.iter()
-                .map(|x| x.path().to_string_lossy().to_string())
+                .map(|x| x.file_name().to_string_lossy().to_string())
                 .filter(|e| filter(e))
-                .map(|e| String::from(e.trim_start_matches("./colors/")))
                 .collect()
         }
         Err(_) => Vec::new(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rslush-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn save_new_file_test() {
+        let path = temp_dir("save-new").join("new.txt");
+        let path = path.to_str().unwrap();
+        let lines = vec![String::from("a"), String::from("b")];
+        assert_eq!(Ok(()), save_file(path, &lines, &FileFormat::default()));
+        assert_eq!("a\nb\n", fs::read_to_string(path).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_keeps_permissions_test() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("save-perm");
+        let path = dir.join("script.sh");
+        fs::write(&path, "old\n").unwrap();
+        fs::set_permissions(&path, Permissions::from_mode(0o751)).unwrap();
+        let path = path.to_str().unwrap();
+        assert_eq!(
+            Ok(()),
+            save_file(path, &[String::from("new")], &FileFormat::default())
+        );
+        assert_eq!("new\n", fs::read_to_string(path).unwrap());
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(0o751, mode & 0o777);
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+    }
+
+    #[test]
+    fn open_test() {
+        let dir = temp_dir("open");
+        let missing = dir.join("missing.txt");
+        let opened = open_file(missing.to_str().unwrap()).unwrap();
+        assert_eq!(("", false), (opened.text.as_str(), opened.read_only));
+        assert!(open_file(dir.to_str().unwrap()).is_err());
+
+        let latin1 = dir.join("latin1.txt");
+        fs::write(&latin1, b"caf\xe9\n").unwrap();
+        let opened = open_file(latin1.to_str().unwrap()).unwrap();
+        assert_eq!(("café", false), (opened.text.as_str(), opened.read_only));
+        assert_eq!(Encoding::Cp1252, opened.format.encoding);
+    }
+
+    #[test]
+    fn encoding_round_trip_test() {
+        let path = temp_dir("encoding").join("utf16.txt");
+        let bytes = b"\xff\xfea\x00\r\x00\n\x00";
+        fs::write(&path, bytes).unwrap();
+        let path = path.to_str().unwrap();
+        let opened = open_file(path).unwrap();
+        assert_eq!("a", opened.text);
+        assert_eq!(Ok(()), save_file(path, &[opened.text], &opened.format));
+        assert_eq!(bytes.to_vec(), fs::read(path).unwrap());
+
+        let format = FileFormat {
+            encoding: Encoding::Cp1252,
+            ..FileFormat::default()
+        };
+        assert!(save_file(path, &[String::from("ż")], &format).is_err());
+        assert_eq!(bytes.to_vec(), fs::read(path).unwrap());
+    }
+
+    #[test]
+    fn diff_test() {
+        let path = temp_dir("diff").join("a.txt");
+        fs::write(&path, "a\nb\n").unwrap();
+        let path = path.to_str().unwrap();
+        let diff = diff(path, &[String::from("a"), String::from("c")]).unwrap();
+        assert_eq!(vec!["@@ -1,2 +1,2 @@", " a", "-b", "+c"], diff[2..]);
+        assert_eq!(
+            Ok(vec![]),
+            super::diff(path, &[String::from("a"), String::from("b")])
+        );
+    }
+
+    #[test]
+    fn save_error_test() {
+        let path = temp_dir("save-err").join("missing").join("x.txt");
+        assert!(save_file(path.to_str().unwrap(), &[], &FileFormat::default()).is_err());
+    }
+}
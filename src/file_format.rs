@@ -0,0 +1,130 @@
+use crate::encoding::Encoding;
+
+const BOM: char = '\u{feff}';
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineEnding {
+    Unix,
+    Dos,
+}
+
+impl LineEnding {
+    /// Parses a `fileformat` value.
+    pub fn from_name(name: &str) -> Result<LineEnding, String> {
+        match name {
+            "unix" => Ok(LineEnding::Unix),
+            "dos" => Ok(LineEnding::Dos),
+            other => Err(format!("Invalid fileformat: {}", other)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            LineEnding::Unix => "unix",
+            LineEnding::Dos => "dos",
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            LineEnding::Unix => "\n",
+            LineEnding::Dos => "\r\n",
+        }
+    }
+}
+
+/// How a file's text was laid out on disk, so saving writes it back the same way.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub bom: bool,
+    pub encoding: Encoding,
+}
+
+impl Default for FileFormat {
+    fn default() -> FileFormat {
+        FileFormat {
+            line_ending: LineEnding::Unix,
+            final_newline: true,
+            bom: false,
+            encoding: Encoding::Utf8,
+        }
+    }
+}
+
+impl FileFormat {
+    /// Detects the format of `text`, returning the text with `\n` line endings and
+    /// without BOM or final newline. A file is `dos` only when every line ends in CRLF.
+    pub fn decode(text: &str) -> (String, FileFormat) {
+        let bom = text.starts_with(BOM);
+        let text = text.strip_prefix(BOM).unwrap_or(text);
+        let newlines = text.matches('\n').count();
+        let line_ending = if newlines > 0 && text.matches("\r\n").count() == newlines {
+            LineEnding::Dos
+        } else {
+            LineEnding::Unix
+        };
+        let final_newline = text.ends_with('\n');
+        let text = text
+            .strip_suffix(line_ending.as_str())
+            .unwrap_or(text)
+            .replace(line_ending.as_str(), "\n");
+        let format = FileFormat {
+            line_ending,
+            final_newline,
+            bom,
+            encoding: Encoding::Utf8,
+        };
+        (text, format)
+    }
+
+    pub fn encode(&self, lines: &[String]) -> String {
+        let mut text = String::new();
+        if self.bom {
+            text.push(BOM);
+        }
+        text += &lines.join(self.line_ending.as_str());
+        if self.final_newline {
+            text += self.line_ending.as_str();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(text: &str) -> String {
+        let (decoded, format) = FileFormat::decode(text);
+        let lines: Vec<String> = decoded.split('\n').map(String::from).collect();
+        format.encode(&lines)
+    }
+
+    #[test]
+    fn decode_test() {
+        let (text, format) = FileFormat::decode("\u{feff}a\r\nb\r\n");
+        assert_eq!("a\nb", text);
+        assert_eq!(
+            FileFormat {
+                line_ending: LineEnding::Dos,
+                final_newline: true,
+                bom: true,
+                encoding: Encoding::Utf8,
+            },
+            format
+        );
+        let (text, format) = FileFormat::decode("a\r\nb\nc");
+        assert_eq!("a\r\nb\nc", text);
+        assert_eq!(LineEnding::Unix, format.line_ending);
+        assert!(!format.final_newline);
+    }
+
+    #[test]
+    fn round_trip_test() {
+        for text in ["a\nb\n", "a\r\nb", "\u{feff}x\r\n", "a\r\nb\n", "", "\n\n"] {
+            assert_eq!(text, round_trip(text));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::{Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+/// External command running in the background. Its stdout replaces `rows`, or is only
+/// displayed when there are none.
+pub struct FilterJob {
+    pub command: String,
+    pub rows: Option<(usize, usize)>,
+    pub input: Vec<String>,
+    child: Child,
+    status: Option<ExitStatus>,
+    stdout: Output,
+    stderr: Output,
+}
+
+/// A pipe of the command, read until it is closed.
+struct Output {
+    receiver: Receiver<Vec<u8>>,
+    bytes: Option<Vec<u8>>,
+}
+
+impl Output {
+    /// Whether the pipe was read to the end.
+    fn poll(&mut self) -> bool {
+        if self.bytes.is_none() {
+            self.bytes = match self.receiver.try_recv() {
+                Ok(bytes) => Some(bytes),
+                Err(TryRecvError::Empty) => None,
+                // the reading thread is gone without sending anything
+                Err(TryRecvError::Disconnected) => Some(Vec::new()),
+            };
+        }
+        self.bytes.is_some()
+    }
+}
+
+fn read_in_background<R: Read + Send + 'static>(mut reader: R) -> Output {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = reader.read_to_end(&mut out);
+        let _ = sender.send(out);
+    });
+    Output {
+        receiver,
+        bytes: None,
+    }
+}
+
+pub fn spawn(
+    command: &str,
+    rows: Option<(usize, usize)>,
+    input: Vec<String>,
+) -> Result<FilterJob, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Unable to run {}: {}", command, e))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let mut text = input.join("\n");
+    if !input.is_empty() {
+        text.push('\n');
+    }
+    // written from a thread so a command that fills its stdout pipe can't block us
+    thread::spawn(move || {
+        let _ = stdin.write_all(text.as_bytes());
+    });
+    let stdout = read_in_background(child.stdout.take().unwrap());
+    let stderr = read_in_background(child.stderr.take().unwrap());
+    Ok(FilterJob {
+        command: command.to_string(),
+        rows,
+        input,
+        child,
+        status: None,
+        stdout,
+        stderr,
+    })
+}
+
+impl FilterJob {
+    /// Output lines once the command exited and closed its output, or its stderr
+    /// when it failed. Never blocks.
+    pub fn poll(&mut self) -> Option<Result<Vec<String>, String>> {
+        if self.status.is_none() {
+            self.status = match self.child.try_wait() {
+                Ok(status) => status,
+                Err(e) => return Some(Err(e.to_string())),
+            };
+        }
+        // both polled, so neither pipe waits on the other
+        let stdout_done = self.stdout.poll();
+        let stderr_done = self.stderr.poll();
+        let status = match self.status {
+            Some(status) if stdout_done && stderr_done => status,
+            _ => return None,
+        };
+        let stdout = self.stdout.bytes.take().unwrap_or_default();
+        let stderr = self.stderr.bytes.take().unwrap_or_default();
+        if status.success() {
+            let text = String::from_utf8_lossy(&stdout);
+            let text = text.strip_suffix('\n').unwrap_or(&text);
+            // no output deletes the lines, as with vim's `!`
+            if stdout.is_empty() {
+                return Some(Ok(Vec::new()));
+            }
+            Some(Ok(text.split('\n').map(|l| l.to_string()).collect()))
+        } else {
+            let stderr = String::from_utf8_lossy(&stderr).trim().to_string();
+            Some(Err(if stderr.is_empty() {
+                format!("{} failed with {}", self.command, status)
+            } else {
+                stderr
+            }))
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn run(command: &str, input: &[&str]) -> Result<Vec<String>, String> {
+        let input = input.iter().map(|l| l.to_string()).collect();
+        let mut job = spawn(command, Some((0, 0)), input).unwrap();
+        loop {
+            if let Some(result) = job.poll() {
+                return result;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn filter_test() {
+        assert_eq!(
+            Ok(vec![String::from("a"), String::from("b")]),
+            run("sort", &["b", "a"])
+        );
+        assert_eq!(
+            Err(String::from("oops")),
+            run("echo oops >&2; exit 3", &["x"])
+        );
+        assert_eq!(Ok(vec![]), run("cat > /dev/null", &["a", "b"]));
+        assert_eq!(Ok(vec![String::new()]), run("echo", &["a"]));
+    }
+
+    #[test]
+    fn late_output_test() {
+        // the shell exits while a background child still holds stdout open
+        let output = run("(sleep 0.7; echo late) &", &[]);
+        assert_eq!(Ok(vec![String::from("late")]), output);
+    }
+}
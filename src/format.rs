@@ -0,0 +1,247 @@
+use regex::Regex;
+use std::cmp::{Ordering, Reverse};
+
+/// Joins `next` onto `line` like `J`, or unchanged like `gJ` when `spaces` is false.
+/// Returns the joined line and the column where the lines meet.
+pub fn join(line: &str, next: &str, spaces: bool) -> (String, usize) {
+    if !spaces {
+        return (format!("{}{}", line, next), line.len());
+    }
+    let next = next.trim_start();
+    if next.is_empty() {
+        (line.to_string(), line.len())
+    } else if line.is_empty() || line.ends_with(char::is_whitespace) || next.starts_with(')') {
+        (format!("{}{}", line, next), line.len())
+    } else {
+        (format!("{} {}", line, next), line.len())
+    }
+}
+
+/// Comment leaders of the `comments` option for filetypes without their own.
+pub const COMMENT_LEADERS: &str = "///,//!,//,#,--,;,*,>";
+
+/// Starts of lines that are code even where `#` begins a comment: Rust attributes
+/// and shebangs.
+const NOT_COMMENTS: [&str; 2] = ["#[", "#!"];
+
+/// Splits a line into its indentation plus comment leader, and the text after it.
+fn split_leader<'a>(line: &'a str, leaders: &[&str]) -> (String, &'a str) {
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let rest = line.trim_start();
+    let leader = match NOT_COMMENTS.iter().any(|n| rest.starts_with(n)) {
+        true => None,
+        false => leaders.iter().find(|l| rest.starts_with(*l)),
+    };
+    match leader {
+        Some(leader) => (
+            format!("{}{} ", indent, leader),
+            rest[leader.len()..].trim_start(),
+        ),
+        None => (indent.to_string(), rest),
+    }
+}
+
+/// Rewraps paragraphs to `width` columns, keeping indentation and the comment leaders
+/// listed in `comments`, e.g. `//,#`. Lines with a different leader or without text
+/// start a new paragraph.
+pub fn reflow(lines: &[String], width: usize, comments: &str) -> Vec<String> {
+    let mut leaders: Vec<&str> = comments.split(',').filter(|l| !l.is_empty()).collect();
+    // `///` before `//`, whatever the order they are listed in
+    leaders.sort_by_key(|l| Reverse(l.len()));
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let (prefix, text) = split_leader(&lines[i], &leaders);
+        if text.is_empty() {
+            result.push(lines[i].trim_end().to_string());
+            i += 1;
+            continue;
+        }
+        let mut words: Vec<&str> = text.split_whitespace().collect();
+        i += 1;
+        while i < lines.len() {
+            let (next_prefix, next_text) = split_leader(&lines[i], &leaders);
+            if next_prefix != prefix || next_text.is_empty() {
+                break;
+            }
+            words.extend(next_text.split_whitespace());
+            i += 1;
+        }
+        let mut current = prefix.clone();
+        for word in words {
+            let at_start = current.len() == prefix.len();
+            if !at_start && current.chars().count() + 1 + word.chars().count() > width {
+                result.push(current);
+                current = prefix.clone();
+            } else if !at_start {
+                current.push(' ');
+            }
+            current += word;
+        }
+        result.push(current);
+    }
+    result
+}
+
+#[derive(Default)]
+pub struct SortOptions {
+    pub reverse: bool,
+    pub numeric: bool,
+    pub unique: bool,
+    pub ignore_case: bool,
+    /// Sort on the text after the match, or on the match itself with `r`.
+    pub pattern: Option<Regex>,
+    pub use_match: bool,
+}
+
+impl SortOptions {
+    /// Parses `:sort[!] [n][u][i][r] [/pattern/]`.
+    pub fn parse(bang: bool, args: &str) -> Result<SortOptions, String> {
+        let mut options = SortOptions {
+            reverse: bang,
+            ..SortOptions::default()
+        };
+        let mut rest = args.trim();
+        while let Some(c) = rest.chars().next() {
+            match c {
+                'n' => options.numeric = true,
+                'u' => options.unique = true,
+                'i' => options.ignore_case = true,
+                'r' => options.use_match = true,
+                ' ' => (),
+                '/' => {
+                    let end = rest[1..]
+                        .find('/')
+                        .ok_or_else(|| String::from("Missing closing / in sort pattern"))?;
+                    let pattern = Regex::new(&rest[1..end + 1]).map_err(|e| e.to_string())?;
+                    options.pattern = Some(pattern);
+                    rest = &rest[end + 2..];
+                    continue;
+                }
+                _ => return Err(format!("Invalid sort argument: {}", rest)),
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+        Ok(options)
+    }
+
+    fn key<'a>(&self, line: &'a str) -> Option<&'a str> {
+        match &self.pattern {
+            Some(re) => re.find(line).map(|m| {
+                if self.use_match {
+                    m.as_str()
+                } else {
+                    &line[m.end()..]
+                }
+            }),
+            None => Some(line),
+        }
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        if self.numeric {
+            first_number(a).cmp(&first_number(b))
+        } else if self.ignore_case {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        } else {
+            a.cmp(b)
+        }
+    }
+}
+
+/// First decimal number in the text, with its minus sign.
+fn first_number(text: &str) -> Option<i64> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let digits = text[start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(text.len(), |len| start + len);
+    let start = if text[..start].ends_with('-') {
+        start - 1
+    } else {
+        start
+    };
+    text[start..digits].parse().ok()
+}
+
+/// Sorts lines like vim's `:sort`. Lines without a pattern match keep their order and
+/// stay before the sorted lines (after them when reversed).
+pub fn sort(lines: &[String], options: &SortOptions) -> Vec<String> {
+    let (mut keyed, unmatched): (Vec<(&str, &String)>, Vec<&String>) = {
+        let mut keyed = Vec::new();
+        let mut unmatched = Vec::new();
+        for l in lines {
+            match options.key(l) {
+                Some(k) => keyed.push((k, l)),
+                None => unmatched.push(l),
+            }
+        }
+        (keyed, unmatched)
+    };
+    keyed.sort_by(|(a, _), (b, _)| {
+        let ord = options.compare(a, b);
+        if options.reverse {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+    if options.unique {
+        keyed.dedup_by(|(a, _), (b, _)| options.compare(a, b) == Ordering::Equal);
+    }
+    let sorted = keyed.into_iter().map(|(_, l)| l.clone());
+    let unmatched = unmatched.into_iter().cloned();
+    if options.reverse {
+        sorted.chain(unmatched.rev()).collect()
+    } else {
+        unmatched.chain(sorted).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(String::from).collect()
+    }
+
+    #[test]
+    fn join_test() {
+        assert_eq!((String::from("foo bar"), 3), join("foo", "    bar", true));
+        assert_eq!((String::from("call(x)"), 6), join("call(x", "  )", true));
+        assert_eq!((String::from("foo "), 4), join("foo ", "", true));
+        assert_eq!((String::from("foo  bar"), 3), join("foo", "  bar", false));
+    }
+
+    #[test]
+    fn reflow_test() {
+        let text = lines("    // one two three\n    // four five six seven\n\nalpha beta gamma");
+        assert_eq!(
+            lines("    // one two\n    // three four\n    // five six\n    // seven\n\nalpha beta gamma"),
+            reflow(&text, 18, COMMENT_LEADERS)
+        );
+        let hash = lines("# a b\n#c d");
+        assert_eq!(lines("# a b c d"), reflow(&hash, 80, COMMENT_LEADERS));
+        assert_eq!(lines("# a b #c d"), reflow(&hash, 80, "//"));
+        let rust = lines("#[derive(Debug)]\n#[serde(default)]\n#![allow(x)]\n// a\n// b");
+        assert_eq!(
+            lines("#[derive(Debug)] #[serde(default)] #![allow(x)]\n// a b"),
+            reflow(&rust, 80, COMMENT_LEADERS)
+        );
+    }
+
+    #[test]
+    fn sort_test() {
+        let text = lines("b10\nA2\na1\nb10\nc");
+        let opts = |bang, args| SortOptions::parse(bang, args).unwrap();
+        assert_eq!(lines("A2\na1\nb10\nb10\nc"), sort(&text, &opts(false, "")));
+        assert_eq!(lines("a1\nA2\nb10\nc"), sort(&text, &opts(false, "iu")));
+        assert_eq!(lines("c\na1\nA2\nb10\nb10"), sort(&text, &opts(false, "n")));
+        assert_eq!(lines("b10\nb10\nA2\na1\nc"), sort(&text, &opts(true, "n")));
+        assert_eq!(
+            lines("c\nA2\na1\nb10\nb10"),
+            sort(&text, &opts(false, r"/\d/"))
+        );
+        assert!(SortOptions::parse(false, "x").is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use sdl2::pixels::Color;
+use std::collections::BTreeMap;
+
+use crate::color::color_to_string;
+
+/// Group every other group falls back to.
+pub const NORMAL: &str = "Normal";
+
+/// Groups known to rslush; a scheme may also define sub-groups like `Keyword.Control`.
+pub const GROUPS: &[&str] = &[
+    "Normal",
+    "Cursor",
+    "StatusLine",
+    "Visual",
+    "LineNr",
+    "Search",
+    "FilePicker",
+    "ItemPicker",
+    "Comment",
+    "Constant",
+    "String",
+    "Number",
+    "Identifier",
+    "Function",
+    "Keyword",
+    "Type",
+    "Operator",
+    "DiagnosticError",
+    "DiagnosticWarning",
+    "DiagnosticInfo",
+    "DiagnosticHint",
+];
+
+/// Links in place for groups a color scheme leaves out.
+const DEFAULT_LINKS: &[(&str, &str)] = &[
+    ("Visual", "FilePicker.Selection"),
+    ("Search", "Visual"),
+    ("ItemPicker", "FilePicker"),
+    ("ItemPicker.Border", "FilePicker.Border"),
+    ("ItemPicker.Selection", "FilePicker.Selection"),
+    ("String", "Constant"),
+    ("Number", "Constant"),
+    ("Function", "Identifier"),
+];
+
+/// Links followed before giving up on a cycle.
+const MAX_LINKS: usize = 16;
+
+/// Attributes set by a group; the others come from its parent.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Attrs {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underline: Option<bool>,
+}
+
+impl Attrs {
+    /// `self` with the attributes it leaves out taken from `parent`.
+    fn or(self, parent: Attrs) -> Attrs {
+        Attrs {
+            fg: self.fg.or(parent.fg),
+            bg: self.bg.or(parent.bg),
+            bold: self.bold.or(parent.bold),
+            italic: self.italic.or(parent.italic),
+            underline: self.underline.or(parent.underline),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Group {
+    Attrs(Attrs),
+    /// Looks the same as another group.
+    Link(String),
+}
+
+/// Resolved look of a group.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    /// Attributes as shown by `:highlight`, e.g. `fg=#FFFFFF bg=#1B2B34 bold`.
+    pub fn describe(&self) -> String {
+        let fg = color_to_string(self.fg);
+        let mut text = format!("fg={} bg={}", fg, color_to_string(self.bg));
+        let flags = [
+            (self.bold, " bold"),
+            (self.italic, " italic"),
+            (self.underline, " underline"),
+        ];
+        for (_, flag) in flags.iter().filter(|(set, _)| *set) {
+            text += flag;
+        }
+        text
+    }
+}
+
+/// Group `Keyword.Control` inherits from `Keyword`, top level groups from `Normal`.
+fn parent(name: &str) -> Option<&str> {
+    match name.rfind('.') {
+        Some(dot) => Some(&name[..dot]),
+        None if name == NORMAL => None,
+        None => Some(NORMAL),
+    }
+}
+
+/// Highlight groups of a color scheme.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Highlights {
+    groups: BTreeMap<String, Group>,
+}
+
+impl Highlights {
+    /// Groups with `normal` colors and the default links.
+    pub fn new(fg: Color, bg: Color) -> Highlights {
+        let mut groups: BTreeMap<String, Group> = DEFAULT_LINKS
+            .iter()
+            .map(|(name, target)| (name.to_string(), Group::Link(target.to_string())))
+            .collect();
+        let normal = Attrs {
+            fg: Some(fg),
+            bg: Some(bg),
+            ..Attrs::default()
+        };
+        groups.insert(String::from(NORMAL), Group::Attrs(normal));
+        Highlights { groups }
+    }
+
+    /// Defines the group, keeping the attributes it had where the new ones are left out.
+    pub fn set(&mut self, name: &str, group: Group) {
+        let group = match (group, self.groups.get(name)) {
+            (Group::Attrs(attrs), Some(Group::Attrs(old))) => Group::Attrs(attrs.or(*old)),
+            (group, _) => group,
+        };
+        self.groups.insert(name.to_string(), group);
+    }
+
+    /// Sets one attribute of the group, keeping the others it defines.
+    pub fn update(&mut self, name: &str, change: impl FnOnce(&mut Attrs)) {
+        let mut attrs = match self.groups.get(name) {
+            Some(Group::Attrs(attrs)) => *attrs,
+            _ => Attrs::default(),
+        };
+        change(&mut attrs);
+        self.groups.insert(name.to_string(), Group::Attrs(attrs));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Group> {
+        self.groups.get(name)
+    }
+
+    /// Links to groups that don't exist and links that lead back to their group.
+    pub fn check_links(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, group) in &self.groups {
+            let mut path = vec![name.as_str()];
+            let mut group = group;
+            while let Group::Link(target) = group {
+                if path.contains(&target.as_str()) {
+                    // reported once, for the first group of the cycle
+                    if target == name && path.iter().all(|g| name.as_str() <= *g) {
+                        problems.push(format!("Link cycle: {} -> {}", path.join(" -> "), name));
+                    }
+                    break;
+                }
+                path.push(target);
+                match self.groups.get(target) {
+                    Some(next) => group = next,
+                    None => {
+                        let root = target.split('.').next().unwrap_or_default();
+                        if !GROUPS.contains(&root) {
+                            problems
+                                .push(format!("Group {} links to unknown group {}", name, target));
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        problems
+    }
+
+    fn attrs(&self, name: &str, links: usize) -> Attrs {
+        let inherited = match parent(name) {
+            Some(parent) if links < MAX_LINKS => self.attrs(parent, links + 1),
+            _ => Attrs::default(),
+        };
+        match self.groups.get(name) {
+            Some(Group::Link(target)) if links < MAX_LINKS => self.attrs(target, links + 1),
+            Some(Group::Attrs(attrs)) => attrs.or(inherited),
+            _ => inherited,
+        }
+    }
+
+    pub fn style(&self, name: &str) -> Style {
+        let attrs = self.attrs(name, 0);
+        let normal = self.attrs(NORMAL, 0);
+        Style {
+            fg: attrs.fg.or(normal.fg).unwrap_or(Color::RGB(255, 255, 255)),
+            bg: attrs.bg.or(normal.bg).unwrap_or(Color::RGB(0, 0, 0)),
+            bold: attrs.bold.unwrap_or(false),
+            italic: attrs.italic.unwrap_or(false),
+            underline: attrs.underline.unwrap_or(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FG: Color = Color::RGB(1, 1, 1);
+    const BG: Color = Color::RGB(2, 2, 2);
+    const RED: Color = Color::RGB(255, 0, 0);
+
+    fn fg(color: Color) -> Group {
+        Group::Attrs(Attrs {
+            fg: Some(color),
+            ..Attrs::default()
+        })
+    }
+
+    #[test]
+    fn inherit_test() {
+        let mut hl = Highlights::new(FG, BG);
+        assert_eq!((FG, BG), (hl.style("Keyword").fg, hl.style("Keyword").bg));
+        hl.set("Keyword", fg(RED));
+        hl.update("Keyword.Control", |a| a.italic = Some(true));
+        let style = hl.style("Keyword.Control");
+        assert_eq!(
+            (RED, BG, true, false),
+            (style.fg, style.bg, style.italic, style.bold)
+        );
+        assert!(!hl.style("Keyword").italic);
+        hl.set(NORMAL, Group::Attrs(Attrs::default()));
+        assert_eq!(BG, hl.style(NORMAL).bg);
+    }
+
+    #[test]
+    fn link_test() {
+        let mut hl = Highlights::new(FG, BG);
+        hl.set("Constant", fg(RED));
+        assert_eq!(RED, hl.style("Number").fg);
+        hl.set("Number", Group::Link(String::from("Comment")));
+        assert_eq!(FG, hl.style("Number").fg);
+        hl.set("Comment", Group::Link(String::from("Number")));
+        assert_eq!(FG, hl.style("Comment").fg);
+    }
+}
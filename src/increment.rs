@@ -0,0 +1,156 @@
+#[derive(PartialEq, Debug)]
+enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    Hex,
+}
+
+struct Number {
+    start: usize,
+    end: usize,
+    radix: Radix,
+}
+
+impl Radix {
+    fn base(&self) -> u32 {
+        match self {
+            Radix::Binary => 2,
+            Radix::Octal => 8,
+            Radix::Decimal => 10,
+            Radix::Hex => 16,
+        }
+    }
+}
+
+/// Number literal starting at `i`, if any: `0x1f`, `0b101`, `0o17`, `-42` or `42`.
+fn number_at(bytes: &[u8], i: usize) -> Option<Number> {
+    let digits_from = |from: usize, radix: Radix| {
+        let len = bytes[from..]
+            .iter()
+            .take_while(|c| (**c as char).is_digit(radix.base()))
+            .count();
+        if len > 0 {
+            Some(Number {
+                start: i,
+                end: from + len,
+                radix,
+            })
+        } else {
+            None
+        }
+    };
+    let prefixed = match (bytes.get(i), bytes.get(i + 1)) {
+        (Some(b'0'), Some(b'x')) | (Some(b'0'), Some(b'X')) => digits_from(i + 2, Radix::Hex),
+        (Some(b'0'), Some(b'b')) | (Some(b'0'), Some(b'B')) => digits_from(i + 2, Radix::Binary),
+        (Some(b'0'), Some(b'o')) | (Some(b'0'), Some(b'O')) => digits_from(i + 2, Radix::Octal),
+        _ => None,
+    };
+    match bytes.get(i) {
+        _ if prefixed.is_some() => prefixed,
+        Some(b'-') if bytes.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+            digits_from(i + 1, Radix::Decimal)
+        }
+        Some(c) if c.is_ascii_digit() => digits_from(i, Radix::Decimal),
+        _ => None,
+    }
+}
+
+/// First number on the line that contains or follows `col`.
+fn find_number(line: &str, col: usize) -> Option<Number> {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match number_at(bytes, i) {
+            Some(n) if n.end > col => return Some(n),
+            Some(n) => i = n.end,
+            None => i += 1,
+        }
+    }
+    None
+}
+
+fn format_number(text: &str, radix: &Radix, delta: i64) -> Option<String> {
+    if *radix == Radix::Decimal {
+        let digits = text.trim_start_matches('-');
+        let value: i128 = text.parse().ok()?;
+        let new = value.checked_add(delta as i128)?;
+        // zero padded numbers keep their width
+        let width = if digits.len() > 1 && digits.starts_with('0') {
+            digits.len()
+        } else {
+            0
+        };
+        let sign = if new < 0 { "-" } else { "" };
+        return Some(format!(
+            "{}{:0width$}",
+            sign,
+            new.unsigned_abs(),
+            width = width
+        ));
+    }
+    let (prefix, digits) = text.split_at(2);
+    let value = u64::from_str_radix(digits, radix.base()).ok()?;
+    let new = value.wrapping_add(delta as u64);
+    let width = digits.len();
+    let formatted = match radix {
+        Radix::Binary => format!("{:0width$b}", new, width = width),
+        Radix::Octal => format!("{:0width$o}", new, width = width),
+        _ if digits.chars().any(|c| c.is_ascii_uppercase()) => {
+            format!("{:0width$X}", new, width = width)
+        }
+        _ => format!("{:0width$x}", new, width = width),
+    };
+    Some(format!("{}{}", prefix, formatted))
+}
+
+/// Adds `delta` to the first number at or after `col`, returning the new line and the
+/// column of the number's last character.
+pub fn increment(line: &str, col: usize, delta: i64) -> Option<(String, usize)> {
+    let number = find_number(line, col)?;
+    let text = &line[number.start..number.end];
+    let formatted = format_number(text, &number.radix, delta)?;
+    let new_line = format!(
+        "{}{}{}",
+        &line[..number.start],
+        formatted,
+        &line[number.end..]
+    );
+    Some((new_line, number.start + formatted.len() - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inc(line: &str, col: usize, delta: i64) -> String {
+        increment(line, col, delta).unwrap().0
+    }
+
+    #[test]
+    fn decimal_test() {
+        assert_eq!("let x = 42;", inc("let x = 41;", 0, 1));
+        assert_eq!("x = -3", inc("x = 2", 0, -5));
+        assert_eq!("x = 3", inc("x = -2", 0, 5));
+        assert_eq!("width: 010", inc("width: 007", 0, 3));
+        assert_eq!("a1 b5", inc("a1 b2", 2, 3));
+        assert_eq!(None, increment("no numbers", 0, 1));
+    }
+
+    #[test]
+    fn radix_test() {
+        assert_eq!("0x10", inc("0x0f", 0, 1));
+        assert_eq!("0x0F", inc("0x1A", 3, -11));
+        assert_eq!("0xffffffffffffffff", inc("0x00", 0, -1));
+        assert_eq!("0b0110", inc("0b0101", 0, 1));
+        assert_eq!("0o10", inc("0o07", 0, 1));
+    }
+
+    #[test]
+    fn cursor_test() {
+        assert_eq!(
+            Some((String::from("v = 100"), 6)),
+            increment("v = 99", 0, 1)
+        );
+    }
+}
@@ -1,7 +1,8 @@
-use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 
 use crate::config::ColorScheme;
+use crate::keymap::Key;
 
 pub struct ItemPicker {
     active: bool,
@@ -17,6 +18,8 @@ pub struct Item {
     pub id: usize,
     pub name: String,
     description: Option<String>,
+    /// Colors shown next to the name, e.g. of a color scheme.
+    pub swatch: Vec<Color>,
 }
 
 impl Item {
@@ -25,89 +28,28 @@ impl Item {
             id,
             name,
             description: None,
+            swatch: Vec::new(),
         }
     }
+
+    pub fn with_swatch(mut self, swatch: Vec<Color>) -> Item {
+        self.swatch = swatch;
+        self
+    }
 }
 
 impl ItemPicker {
-    pub fn try_handle_key(&mut self, keycode: &Keycode, shift: bool, ctrl: bool) -> bool {
-        let mut handled = true;
-        if ctrl {
-            match &keycode {
-                Keycode::J => self.selection_down(),
-                Keycode::K => self.selection_up(),
-                Keycode::H => self.delete_char(),
-                Keycode::L => self.accept_selection(),
-                _ => handled = false,
-            }
-        } else {
-            match &keycode {
-                Keycode::Num1 => self.insert_char(shft('1', '!', shift)),
-                Keycode::Num2 => self.insert_char(shft('2', '@', shift)),
-                Keycode::Num3 => self.insert_char(shft('3', '#', shift)),
-                Keycode::Num4 => self.insert_char(shft('4', '$', shift)),
-                Keycode::Num5 => self.insert_char(shft('5', '%', shift)),
-                Keycode::Num6 => self.insert_char(shft('6', '^', shift)),
-                Keycode::Num7 => self.insert_char(shft('7', '&', shift)),
-                Keycode::Num8 => self.insert_char(shft('8', '*', shift)),
-                Keycode::Num9 => self.insert_char(shft('9', '(', shift)),
-                Keycode::Num0 => self.insert_char(shft('0', ')', shift)),
-                Keycode::Minus => self.insert_char(shft('-', '_', shift)),
-                Keycode::Equals => self.insert_char(shft('=', '+', shift)),
-
-                // top row
-                Keycode::Q => self.insert_char(shft('q', 'Q', shift)),
-                Keycode::W => self.insert_char(shft('w', 'W', shift)),
-                Keycode::E => self.insert_char(shft('e', 'E', shift)),
-                Keycode::R => self.insert_char(shft('r', 'R', shift)),
-                Keycode::T => self.insert_char(shft('t', 'T', shift)),
-                Keycode::Y => self.insert_char(shft('y', 'Y', shift)),
-                Keycode::U => self.insert_char(shft('u', 'U', shift)),
-                Keycode::I => self.insert_char(shft('i', 'I', shift)),
-                Keycode::O => self.insert_char(shft('o', 'O', shift)),
-                Keycode::P => self.insert_char(shft('p', 'P', shift)),
-                Keycode::LeftBracket => self.insert_char(shft('[', '{', shift)),
-                Keycode::RightBracket => self.insert_char(shft(']', '}', shift)),
-                Keycode::Backslash => self.insert_char(shft('\\', '|', shift)),
-
-                // middle row
-                Keycode::A => self.insert_char(shft('a', 'A', shift)),
-                Keycode::S => self.insert_char(shft('s', 'S', shift)),
-                Keycode::D => self.insert_char(shft('d', 'D', shift)),
-                Keycode::F => self.insert_char(shft('f', 'F', shift)),
-                Keycode::G => self.insert_char(shft('g', 'G', shift)),
-                Keycode::H => self.insert_char(shft('h', 'H', shift)),
-                Keycode::J => self.insert_char(shft('j', 'J', shift)),
-                Keycode::K => self.insert_char(shft('k', 'K', shift)),
-                Keycode::L => self.insert_char(shft('l', 'L', shift)),
-                Keycode::Semicolon => self.insert_char(shft(';', ':', shift)),
-                Keycode::Quote => self.insert_char(shft('\'', '"', shift)),
-
-                // bottom row
-                Keycode::Z => self.insert_char(shft('z', 'Z', shift)),
-                Keycode::X => self.insert_char(shft('x', 'X', shift)),
-                Keycode::C => self.insert_char(shft('c', 'C', shift)),
-                Keycode::V => self.insert_char(shft('v', 'V', shift)),
-                Keycode::B => self.insert_char(shft('b', 'B', shift)),
-                Keycode::N => self.insert_char(shft('n', 'N', shift)),
-                Keycode::M => self.insert_char(shft('m', 'M', shift)),
-                Keycode::Comma => self.insert_char(shft(',', '<', shift)),
-                Keycode::Period => self.insert_char(shft('.', '>', shift)),
-                Keycode::Slash => self.insert_char(shft('/', '?', shift)),
-
-                // other
-                Keycode::Space => self.insert_char(' '),
-
-                Keycode::Return => self.accept_selection(),
-                Keycode::Backspace => self.delete_char(),
-                Keycode::Up => self.selection_up(),
-                Keycode::Down => self.selection_down(),
-                Keycode::Escape => self.deactivate(),
-
-                _ => handled = false,
-            }
+    pub fn try_handle_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Ctrl('j') | Key::Down => self.selection_down(),
+            Key::Ctrl('k') | Key::Up => self.selection_up(),
+            Key::Ctrl('h') | Key::Backspace => self.delete_char(),
+            Key::Ctrl('l') | Key::Enter => self.accept_selection(),
+            Key::Escape => self.deactivate(),
+            Key::Char(c) => self.insert_char(c),
+            _ => return false,
         }
-        handled
+        true
     }
 }
 
@@ -154,6 +96,10 @@ impl ItemPicker {
         &self.filtered
     }
 
+    pub fn selected(&self) -> Option<&Item> {
+        self.filtered.get(self.selected_line)
+    }
+
     pub fn selection_up(&mut self) {
         if self.filtered.len() > self.selected_line + 1 {
             self.selected_line += 1;
@@ -211,9 +157,10 @@ impl ItemPickerDisplay {
         font: &sdl2::ttf::Font,
         texture_creator: &sdl2::render::TextureCreator<sdl2::video::WindowContext>,
     ) {
-        canvas.set_draw_color(cs.itempicker_bg);
+        let style = cs.style("ItemPicker");
+        canvas.set_draw_color(style.bg);
         canvas.fill_rect(draw_area).unwrap();
-        canvas.set_draw_color(cs.itempicker_border);
+        canvas.set_draw_color(cs.style("ItemPicker.Border").fg);
         canvas.draw_rect(draw_area).unwrap();
 
         let items_len = picker.get_items().len();
@@ -228,7 +175,7 @@ impl ItemPickerDisplay {
             picker.inserted_part()
         );
         let rendering = font.render(&prompt);
-        let surface = rendering.blended(cs.filepicker_fg).unwrap();
+        let surface = rendering.blended(style.fg).unwrap();
         let texture = surface.as_texture(&texture_creator).unwrap();
         let mut r = Rect::new(
             draw_area.x(),
@@ -250,26 +197,32 @@ impl ItemPickerDisplay {
             .enumerate()
         {
             if id == picker.selected_line {
-                canvas.set_draw_color(cs.itempicker_selection);
+                canvas.set_draw_color(cs.style("ItemPicker.Selection").bg);
                 canvas
                     .fill_rect(Rect::new(r.x() + 2, i, draw_area.width() - 4, char_size_y))
                     .unwrap();
             }
             let rendering = font.render(&e.name);
-            let surface = rendering.blended(cs.itempicker_fg).unwrap();
+            let surface = rendering.blended(style.fg).unwrap();
             let texture = surface.as_texture(&texture_creator).unwrap();
             let rt = Rect::new(r.x() + 5, i, surface.width(), surface.height());
             canvas.copy(&texture, None, rt).unwrap();
 
+            // swatch strip at the right end of the row
+            let cell = char_size_y.saturating_sub(4).max(1);
+            let strip = cell * e.swatch.len() as u32;
+            let x = draw_area.right() - 5 - strip as i32;
+            for (n, color) in e.swatch.iter().enumerate() {
+                canvas.set_draw_color(*color);
+                let at = x + (cell * n as u32) as i32;
+                canvas.fill_rect(Rect::new(at, i + 2, cell, cell)).unwrap();
+            }
+            if !e.swatch.is_empty() {
+                canvas.set_draw_color(cs.style("ItemPicker.Border").fg);
+                canvas.draw_rect(Rect::new(x, i + 2, strip, cell)).unwrap();
+            }
+
             i -= char_size_y as i32;
         }
     }
 }
-
-fn shft(no_shift: char, shift: char, is_shift: bool) -> char {
-    if is_shift {
-        shift
-    } else {
-        no_shift
-    }
-}
@@ -0,0 +1,421 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Bound::{Excluded, Unbounded};
+use std::time::{Duration, Instant};
+
+/// A key press, written like `a`, `<C-o>` or `<CR>` in mappings.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Key {
+    Char(char),
+    /// Ctrl with a lowercase letter.
+    Ctrl(char),
+    Enter,
+    Escape,
+    Backspace,
+    Tab,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Key {
+    /// Character typed by the key; Ctrl+letter gives a control character.
+    pub fn to_char(self) -> Option<char> {
+        match self {
+            Key::Char(c) => Some(c),
+            Key::Ctrl(c) => Some((c as u8 - b'a' + 1) as char),
+            _ => None,
+        }
+    }
+
+    fn named(name: &str, leader: &[Key]) -> Result<Vec<Key>, String> {
+        let lower = name.to_ascii_lowercase();
+        let key = match lower.as_str() {
+            "leader" => return Ok(leader.to_vec()),
+            "cr" | "enter" | "return" => Key::Enter,
+            "esc" => Key::Escape,
+            "bs" | "backspace" => Key::Backspace,
+            "tab" => Key::Tab,
+            "space" => Key::Char(' '),
+            "lt" => Key::Char('<'),
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            _ => match lower
+                .strip_prefix("c-")
+                .map(|c| c.chars().collect::<Vec<_>>())
+            {
+                Some(c) if c.len() == 1 && c[0].is_ascii_alphabetic() => Key::Ctrl(c[0]),
+                _ => return Err(format!("Unknown key <{}>", name)),
+            },
+        };
+        Ok(vec![key])
+    }
+
+    /// Keys written in `text`, with `<Leader>` replaced by `leader`.
+    pub fn parse_seq(text: &str, leader: &[Key]) -> Result<Vec<Key>, String> {
+        let mut keys = Vec::new();
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            let name = rest.strip_prefix('<').and_then(|r| r.split_once('>'));
+            match name {
+                Some((name, after)) if !name.is_empty() && !name.contains('<') => {
+                    keys.extend(Key::named(name, leader)?);
+                    rest = after;
+                }
+                _ => {
+                    keys.push(Key::Char(c));
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+        Ok(keys)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Char(' ') => write!(f, "<Space>"),
+            Key::Char('<') => write!(f, "<lt>"),
+            Key::Char(c) => write!(f, "{}", c),
+            Key::Ctrl(c) => write!(f, "<C-{}>", c),
+            Key::Enter => write!(f, "<CR>"),
+            Key::Escape => write!(f, "<Esc>"),
+            Key::Backspace => write!(f, "<BS>"),
+            Key::Tab => write!(f, "<Tab>"),
+            Key::Up => write!(f, "<Up>"),
+            Key::Down => write!(f, "<Down>"),
+            Key::Left => write!(f, "<Left>"),
+            Key::Right => write!(f, "<Right>"),
+        }
+    }
+}
+
+fn show(keys: &[Key]) -> String {
+    keys.iter().map(|k| k.to_string()).collect()
+}
+
+/// Which table a key is looked up in.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+    Command,
+    /// The file and item pickers.
+    Picker,
+}
+
+const MODES: [(Mode, &str); 5] = [
+    (Mode::Normal, "n"),
+    (Mode::Insert, "i"),
+    (Mode::Visual, "v"),
+    (Mode::Command, "c"),
+    (Mode::Picker, "p"),
+];
+
+impl Mode {
+    /// Modes of a map command like `nmap`, `unmap` or `pnoremap`.
+    pub fn of_command(name: &str) -> Option<(Vec<Mode>, bool)> {
+        let (prefix, unmap) = match name.strip_suffix("unmap") {
+            Some(prefix) => (prefix, true),
+            None => (name.strip_suffix("map")?.trim_end_matches("nore"), false),
+        };
+        let modes = match prefix {
+            "" => vec![Mode::Normal, Mode::Visual],
+            _ => vec![MODES.iter().find(|(_, p)| *p == prefix)?.0],
+        };
+        Some((modes, unmap))
+    }
+}
+
+/// What a key produces once mappings are applied.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Step {
+    /// Handled by the mode like a typed key, without further mapping.
+    Key(Key),
+    /// Run as with `:`, whatever the mode.
+    Ex(String),
+}
+
+#[derive(Clone, Debug)]
+struct Mapping {
+    rhs: String,
+    steps: Vec<Step>,
+}
+
+/// Mappings from the `keymaps` section of the config, by mode.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeymapConfig {
+    /// Keys for `<Leader>`, `\` by default.
+    pub leader: Option<String>,
+    pub normal: BTreeMap<String, String>,
+    pub insert: BTreeMap<String, String>,
+    pub visual: BTreeMap<String, String>,
+    pub command: BTreeMap<String, String>,
+    pub picker: BTreeMap<String, String>,
+}
+
+/// Ex commands bound by the `default` profile in every mode.
+const DEFAULTS: &[(&str, &str)] = &[
+    ("<C-o>", ":files<CR>"),
+    ("<C-p>", ":schemefiles<CR>"),
+    ("<C-b>", ":reloadscheme<CR>"),
+    ("<C-n>", ":colorscheme<CR>"),
+    ("<C-m>", ":commands<CR>"),
+];
+
+pub struct Keymaps {
+    tables: [BTreeMap<Vec<Key>, Mapping>; 5],
+    leader: Vec<Key>,
+    pending: Vec<Key>,
+    pending_mode: Mode,
+    pending_since: Instant,
+}
+
+impl Default for Keymaps {
+    fn default() -> Keymaps {
+        Keymaps::new("default", &KeymapConfig::default()).0
+    }
+}
+
+impl Keymaps {
+    /// Mappings of the profile with the config's added, and the invalid ones.
+    pub fn new(profile: &str, config: &KeymapConfig) -> (Keymaps, Vec<String>) {
+        let mut errors = Vec::new();
+        let leader = match config.leader.as_deref().map(|l| Key::parse_seq(l, &[])) {
+            Some(Ok(leader)) if !leader.is_empty() => leader,
+            Some(_) => {
+                errors.push(String::from("Invalid leader key"));
+                vec![Key::Char('\\')]
+            }
+            None => vec![Key::Char('\\')],
+        };
+        let mut keymaps = Keymaps {
+            tables: Default::default(),
+            leader,
+            pending: Vec::new(),
+            pending_mode: Mode::Normal,
+            pending_since: Instant::now(),
+        };
+        if profile == "default" {
+            for (mode, _) in MODES.iter() {
+                for (lhs, rhs) in DEFAULTS {
+                    keymaps.map(*mode, lhs, rhs).unwrap();
+                }
+            }
+        }
+        let sections = [
+            (Mode::Normal, &config.normal),
+            (Mode::Insert, &config.insert),
+            (Mode::Visual, &config.visual),
+            (Mode::Command, &config.command),
+            (Mode::Picker, &config.picker),
+        ];
+        for (mode, section) in sections.iter() {
+            for (lhs, rhs) in section.iter() {
+                if let Err(msg) = keymaps.map(*mode, lhs, rhs) {
+                    errors.push(msg);
+                }
+            }
+        }
+        (keymaps, errors)
+    }
+
+    fn table(&self, mode: Mode) -> &BTreeMap<Vec<Key>, Mapping> {
+        &self.tables[mode as usize]
+    }
+
+    /// Maps `lhs` to `rhs`. An `rhs` of the form `:command<CR>` runs the command
+    /// in any mode, anything else is typed as keys.
+    pub fn map(&mut self, mode: Mode, lhs: &str, rhs: &str) -> Result<(), String> {
+        let keys = Key::parse_seq(lhs, &self.leader)?;
+        if keys.is_empty() {
+            return Err(String::from("Missing keys to map"));
+        }
+        let command = rhs.strip_prefix(':').and_then(|r| {
+            let lower = r.to_ascii_lowercase();
+            let end = ["<cr>", "<enter>", "<return>"]
+                .iter()
+                .find(|end| lower.ends_with(*end))?;
+            Some(&r[..r.len() - end.len()])
+        });
+        let steps = match command {
+            Some(command) if !command.to_ascii_lowercase().contains("<cr>") => {
+                vec![Step::Ex(command.to_string())]
+            }
+            _ => Key::parse_seq(rhs, &self.leader)?
+                .into_iter()
+                .map(Step::Key)
+                .collect(),
+        };
+        let mapping = Mapping {
+            rhs: rhs.to_string(),
+            steps,
+        };
+        self.tables[mode as usize].insert(keys, mapping);
+        Ok(())
+    }
+
+    pub fn unmap(&mut self, mode: Mode, lhs: &str) -> Result<(), String> {
+        let keys = Key::parse_seq(lhs, &self.leader)?;
+        match self.tables[mode as usize].remove(&keys) {
+            Some(_) => Ok(()),
+            None => Err(format!("No such mapping: {}", lhs)),
+        }
+    }
+
+    /// Every mapping as `mode lhs rhs`.
+    pub fn list(&self) -> Vec<String> {
+        MODES
+            .iter()
+            .flat_map(|(mode, prefix)| {
+                self.table(*mode)
+                    .iter()
+                    .map(move |(keys, m)| format!("{} {} {}", prefix, show(keys), m.rhs))
+            })
+            .collect()
+    }
+
+    /// Keys typed so far of a mapping that isn't complete yet.
+    pub fn pending(&self) -> String {
+        show(&self.pending)
+    }
+
+    /// Takes a key typed in `mode` and returns what to do, nothing while the keys
+    /// may still become a longer mapping.
+    pub fn feed(&mut self, mode: Mode, key: Key, now: Instant) -> Vec<Step> {
+        let mut steps = Vec::new();
+        if mode != self.pending_mode {
+            steps = self.resolve(false);
+        }
+        self.pending.push(key);
+        self.pending_mode = mode;
+        self.pending_since = now;
+        steps.extend(self.resolve(true));
+        steps
+    }
+
+    /// Gives up waiting for the rest of a mapping after `timeout`.
+    pub fn flush(&mut self, now: Instant, timeout: Duration) -> Vec<Step> {
+        if self.pending.is_empty() || now.duration_since(self.pending_since) < timeout {
+            return Vec::new();
+        }
+        self.resolve(false)
+    }
+
+    /// Replaces pending keys by the longest mappings they start with, passing on
+    /// keys that are not mapped.
+    fn resolve(&mut self, wait: bool) -> Vec<Step> {
+        let mut steps = Vec::new();
+        let table = &self.tables[self.pending_mode as usize];
+        while !self.pending.is_empty() {
+            let longer = table
+                .range((Excluded(self.pending.clone()), Unbounded))
+                .next()
+                .is_some_and(|(keys, _)| keys.starts_with(&self.pending));
+            if wait && longer {
+                break;
+            }
+            let mapped = (1..=self.pending.len())
+                .rev()
+                .find_map(|n| Some((n, table.get(&self.pending[..n])?)));
+            match mapped {
+                Some((n, mapping)) => {
+                    steps.extend(mapping.steps.iter().cloned());
+                    self.pending.drain(..n);
+                }
+                None => steps.push(Step::Key(self.pending.remove(0))),
+            }
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(text: &str) -> Vec<Key> {
+        Key::parse_seq(text, &[Key::Char(' ')]).unwrap()
+    }
+
+    fn typed(text: &str) -> Vec<Step> {
+        keys(text).into_iter().map(Step::Key).collect()
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(
+            vec![Key::Ctrl('o'), Key::Char('a'), Key::Enter, Key::Char('<')],
+            keys("<C-O>a<cr><")
+        );
+        assert_eq!(vec![Key::Char(' '), Key::Char('w')], keys("<leader>w"));
+        assert_eq!(vec![Key::Char('<'), Key::Char('>')], keys("<>"));
+        assert!(Key::parse_seq("<C-1>", &[]).is_err());
+        assert_eq!("<C-o>a<CR><lt><Space>", show(&keys("<C-o>a<CR><lt> ")));
+        assert_eq!(Some('\x12'), Key::Ctrl('r').to_char());
+    }
+
+    #[test]
+    fn map_command_test() {
+        assert_eq!(
+            Some((vec![Mode::Normal, Mode::Visual], false)),
+            Mode::of_command("map")
+        );
+        assert_eq!(
+            Some((vec![Mode::Insert], false)),
+            Mode::of_command("inoremap")
+        );
+        assert_eq!(Some((vec![Mode::Picker], true)), Mode::of_command("punmap"));
+        assert_eq!(None, Mode::of_command("xmap"));
+        assert_eq!(None, Mode::of_command("set"));
+    }
+
+    #[test]
+    fn feed_test() {
+        let config = KeymapConfig {
+            leader: Some(String::from("<Space>")),
+            insert: vec![(String::from("jk"), String::from("<Esc>"))]
+                .into_iter()
+                .collect(),
+            ..KeymapConfig::default()
+        };
+        let (mut keymaps, errors) = Keymaps::new("none", &config);
+        assert!(errors.is_empty());
+        keymaps.map(Mode::Normal, "<leader>w", ":w<CR>").unwrap();
+        keymaps.map(Mode::Normal, "<leader>", "dd").unwrap();
+        let now = Instant::now();
+        let feed = |keymaps: &mut Keymaps, mode, text: &str| -> Vec<Step> {
+            keys(text)
+                .into_iter()
+                .flat_map(|key| keymaps.feed(mode, key, now))
+                .collect()
+        };
+        let w = vec![Step::Ex(String::from("w"))];
+        assert_eq!(w, feed(&mut keymaps, Mode::Normal, " w"));
+        assert_eq!(typed("ddx"), feed(&mut keymaps, Mode::Normal, " x"));
+        assert_eq!(typed("a<Esc>"), feed(&mut keymaps, Mode::Insert, "ajk"));
+        assert_eq!(typed(""), feed(&mut keymaps, Mode::Insert, "j"));
+        assert_eq!("j", keymaps.pending());
+        assert!(keymaps.flush(now, Duration::from_secs(1)).is_empty());
+        let later = now + Duration::from_secs(1);
+        assert_eq!(typed("j"), keymaps.flush(later, Duration::from_secs(1)));
+        // unmapped in visual mode
+        assert_eq!(typed(" w"), feed(&mut keymaps, Mode::Visual, " w"));
+        keymaps.unmap(Mode::Normal, "<leader>w").unwrap();
+        assert!(keymaps.unmap(Mode::Normal, "<leader>w").is_err());
+        assert_eq!(vec!["n <Space> dd", "i jk <Esc>"], keymaps.list());
+    }
+
+    #[test]
+    fn default_profile_test() {
+        let keymaps = Keymaps::default();
+        assert!(keymaps.list().contains(&String::from("p <C-o> :files<CR>")));
+    }
+}
@@ -0,0 +1,126 @@
+/// How buffer lines map to cells on screen.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Layout {
+    pub tabstop: usize,
+    /// Cells available for text, or `None` when long lines are cut off.
+    pub wrap_width: Option<usize>,
+}
+
+impl Layout {
+    /// Screen column of the character at byte offset `col`, with tabs expanded.
+    pub fn display_col(&self, line: &str, col: usize) -> usize {
+        let mut cells = 0;
+        for (_, c) in line.char_indices().take_while(|(i, _)| *i < col) {
+            cells += match c {
+                '\t' => self.tabstop - cells % self.tabstop,
+                _ => 1,
+            };
+        }
+        // the cursor may be one past the end of the line
+        cells + col.saturating_sub(line.len())
+    }
+
+    pub fn expand_tabs(&self, line: &str) -> String {
+        let mut expanded = String::with_capacity(line.len());
+        for c in line.chars() {
+            match c {
+                '\t' => {
+                    let cells = self.tabstop - expanded.chars().count() % self.tabstop;
+                    expanded.extend(std::iter::repeat_n(' ', cells));
+                }
+                c => expanded.push(c),
+            }
+        }
+        expanded
+    }
+
+    /// Screen rows taken by the line.
+    pub fn rows(&self, line: &str) -> usize {
+        match self.wrap_width {
+            Some(width) => usize::max(1, self.expand_tabs(line).chars().count().div_ceil(width)),
+            None => 1,
+        }
+    }
+
+    /// Screen row within the line and screen column of the character at `col`.
+    pub fn position(&self, line: &str, col: usize) -> (usize, usize) {
+        let cells = self.display_col(line, col);
+        match self.wrap_width {
+            Some(width) => (cells / width, cells % width),
+            None => (0, cells),
+        }
+    }
+
+    /// Text of every screen row of the line.
+    pub fn segments(&self, line: &str) -> Vec<String> {
+        let expanded: Vec<char> = self.expand_tabs(line).chars().collect();
+        match self.wrap_width {
+            Some(width) if expanded.len() > width => {
+                expanded.chunks(width).map(|c| c.iter().collect()).collect()
+            }
+            _ => vec![expanded.into_iter().collect()],
+        }
+    }
+}
+
+/// First line to show so `row` has `scrolloff` lines around it, starting from `top`.
+pub fn scroll(
+    lines: &[String],
+    top: usize,
+    row: usize,
+    height: usize,
+    scrolloff: usize,
+    layout: &Layout,
+) -> usize {
+    let scrolloff = usize::min(scrolloff, height.saturating_sub(1) / 2);
+    let mut top = usize::min(top, row.saturating_sub(scrolloff));
+    let last = usize::min(row + scrolloff, lines.len().saturating_sub(1));
+    let rows = |top: usize| -> usize { lines[top..=last].iter().map(|l| layout.rows(l)).sum() };
+    while top < row && rows(top) > height {
+        top += 1;
+    }
+    top
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUT: Layout = Layout {
+        tabstop: 4,
+        wrap_width: None,
+    };
+    const WRAP: Layout = Layout {
+        tabstop: 4,
+        wrap_width: Some(5),
+    };
+
+    #[test]
+    fn tabs_test() {
+        assert_eq!("a   b       c", CUT.expand_tabs("a\tb\t\tc"));
+        assert_eq!(4, CUT.display_col("a\tb", 2));
+        assert_eq!(5, CUT.display_col("a\tb", 3));
+        assert_eq!(7, CUT.display_col("a\tb", 5));
+        assert_eq!(2, CUT.display_col("ąb", 3));
+        assert_eq!(vec![String::from("a   b")], CUT.segments("a\tb"));
+    }
+
+    #[test]
+    fn wrap_test() {
+        assert_eq!(1, WRAP.rows(""));
+        assert_eq!(2, WRAP.rows("abcdef"));
+        assert_eq!((1, 1), WRAP.position("abcdef", 6));
+        assert_eq!(vec!["abcde", "f"], WRAP.segments("abcdef"));
+    }
+
+    #[test]
+    fn scroll_test() {
+        let lines: Vec<String> = (0..20).map(|i| format!("line {}", i)).collect();
+        assert_eq!(5, scroll(&lines, 0, 9, 5, 0, &CUT));
+        assert_eq!(7, scroll(&lines, 0, 9, 5, 2, &CUT));
+        assert_eq!(3, scroll(&lines, 5, 3, 5, 0, &CUT));
+        assert_eq!(1, scroll(&lines, 5, 3, 5, 2, &CUT));
+        // every line takes two rows
+        assert_eq!(13, scroll(&lines, 0, 14, 5, 0, &WRAP));
+    }
+}
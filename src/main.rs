@@ -1,20 +1,63 @@
 extern crate sdl2;
 
+mod assets;
 mod buffer;
+mod cli;
+mod color;
 mod config;
+mod encoding;
+mod ex;
 mod file_assist;
+mod file_format;
 mod file_picker;
+mod filter;
+mod format;
+mod highlight;
+mod increment;
 mod item_picker;
+mod keymap;
+mod layout;
+mod motion;
+mod normal;
+mod recovery;
+mod session;
+mod surround;
+mod swap;
+mod text_object;
+mod theme_import;
+mod undo;
+mod watcher;
 
 use buffer::*;
+use cli::{Cli, FileArg};
 use config::*;
+use encoding::Encoding;
+use file_format::LineEnding;
+use filter::FilterJob;
+use highlight::{Group, Style};
+use keymap::{Key, Keymaps, Step};
+use layout::Layout;
+use motion::Motion;
+use normal::{Action, Command, Parsed, VisualAction};
+use session::{BufferSession, Session};
+use text_object::{Pos, Range};
+use watcher::FileWatcher;
 use ItemPickerHandler::*;
 
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 use sdl2::keyboard::Mod;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
+use sdl2::rwops::RWops;
+use sdl2::surface::Surface;
+use sdl2::ttf::{Font, FontStyle, Sdl2TtfContext};
+use sdl2::video::{Window, WindowPos};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::time::Instant;
 
@@ -23,11 +66,22 @@ use item_picker::*;
 
 const STARTX: u32 = 0;
 const STARTY: u32 = 0;
+const SWAP_INTERVAL: Duration = Duration::from_secs(4);
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 enum InputMode {
     Insert,
     Normal,
+    Visual,
+    Replace,
+    Command,
+}
+
+/// Input of a frame, handled in order.
+enum Typed {
+    Key(Key),
+    /// Text committed by an input method.
+    Composed(String),
 }
 
 enum FilePickerAction {
@@ -49,19 +103,99 @@ impl CommandAction {
     }
 }
 
+/// Color schemes listed by `:colorscheme`, applied while they are selected.
+struct SchemePreview {
+    names: Vec<String>,
+    schemes: Vec<Result<ColorScheme, String>>,
+    /// Scheme in use before the picker opened, restored when it is closed.
+    previous: ColorScheme,
+}
+
 enum ItemPickerHandler {
-    ChangeColorScheme(Vec<String>),
+    ChangeColorScheme(SchemePreview),
     ExecuteCommand,
+    RecoverSwap(swap::Swap),
+    RecoveredFiles(Vec<recovery::Dump>),
+    RecoveryAction(recovery::Dump),
+    ExternalChange,
+    ExternalDiff,
+}
+
+/// Leaves the item picker without choosing, undoing a color scheme preview.
+fn item_picker_cancel(app: &mut App) {
+    if let Some(ChangeColorScheme(preview)) = app.item_action.take() {
+        app.cs = preview.previous;
+    }
+}
+
+/// Applies the color scheme selected in the `:colorscheme` picker.
+fn preview_color_scheme(app: &mut App) {
+    if let Some(ChangeColorScheme(preview)) = &app.item_action {
+        let selected = app.item_picker.selected().map(|item| item.id);
+        app.cs = match selected.map(|id| &preview.schemes[id]) {
+            Some(Ok(cs)) => cs.clone(),
+            Some(Err(msg)) => {
+                app.message = Some(msg.clone());
+                preview.previous.clone()
+            }
+            None => preview.previous.clone(),
+        };
+    }
 }
 
-fn item_picker_action(item: Item, app: &mut App) {
+fn item_picker_action(item: Item, buffer: &mut Buffer, app: &mut App) {
     println!("Executing action {}", item.name);
-    match app.item_action.as_ref() {
-        Some(ChangeColorScheme(colors)) => {
-            let cs_path = format!("./colors/{}", colors[item.id].clone());
-            app.set_color_scheme(cs_path);
+    match app.item_action.take() {
+        Some(ChangeColorScheme(preview)) => {
+            app.set_color_scheme(preview.names[item.id].clone());
         }
         Some(ExecuteCommand) => (),
+        Some(RecoveredFiles(mut dumps)) => {
+            let dump = dumps.remove(item.id);
+            app.display_message(dump.describe());
+            let choices = ["Restore", "Diff against disk", "Discard"];
+            let items = choices
+                .iter()
+                .enumerate()
+                .map(|(i, c)| Item::new(i, c.to_string()))
+                .collect();
+            app.item_picker.activate(items);
+            app.item_action = Some(RecoveryAction(dump));
+        }
+        Some(RecoveryAction(dump)) => recovery_action(item.id, dump, buffer, app),
+        Some(ExternalChange) => match item.id {
+            0 => reload_buffer(buffer, app),
+            1 => (),
+            _ => match file_assist::diff(&buffer.file_name, &buffer.lines) {
+                Ok(diff) => {
+                    app.display_message(String::from("Disk (-) against buffer (+)"));
+                    let items = diff
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, l)| Item::new(i, l))
+                        .collect();
+                    app.item_picker.activate(items);
+                    app.item_action = Some(ExternalDiff);
+                }
+                Err(msg) => app.display_message(msg),
+            },
+        },
+        Some(ExternalDiff) => ask_external_change(buffer, app),
+        Some(RecoverSwap(swap)) => match item.id {
+            0 => {
+                buffer.lines = swap.lines;
+                buffer.modified = true;
+                buffer.set_cursor_pos(Pos::new(0, 0));
+                let _ = std::fs::remove_file(&swap.path);
+                app.display_message(String::from("Recovered, save to keep the changes"));
+            }
+            1 => buffer.read_only = true,
+            _ => {
+                if let Err(e) = std::fs::remove_file(&swap.path) {
+                    app.display_message(format!("Unable to delete {}: {}", swap.path.display(), e));
+                }
+            }
+        },
         _ => (),
     }
 }
@@ -74,58 +208,238 @@ struct App {
     // ** ItemPicker **
     pub item_picker: ItemPicker,
     pub item_action: Option<ItemPickerHandler>,
+
+    // ** Command line **
+    pub command_line: String,
+    pub visual_marks: Option<(usize, usize)>,
+    pub options: Options,
+
+    // ** Config **
+    pub config: Config,
+    pub config_path: Option<PathBuf>,
+    pub font_request: bool,
+
+    // ** Keymaps **
+    pub keymaps: Keymaps,
+    pub file_picker_request: Option<FilePickerAction>,
+
+    // ** Messages and background jobs **
+    pub message: Option<String>,
+    pub filter_job: Option<FilterJob>,
+
+    // ** Swap file **
+    pub swap_written: Instant,
+    pub swap_hash: u64,
+    /// Quit was asked for with unsaved changes, asking again discards them.
+    pub quit_requested: bool,
+
+    // ** File on disk **
+    pub watcher: Option<FileWatcher>,
+
+    // ** Session **
+    pub session_request: Option<PathBuf>,
+
+    // ** Argument list **
+    pub arg_list: Vec<FileArg>,
+    pub arg_index: usize,
+    pub arg_read_only: bool,
 }
 
 impl App {
     fn new() -> App {
         App {
-            cs: DEFAULT_CS,
+            cs: ColorScheme::default(),
             cs_path: None,
             item_picker: ItemPicker::new(),
             item_action: None,
+            command_line: String::new(),
+            visual_marks: None,
+            options: Options::default(),
+            config: Config::default(),
+            config_path: None,
+            font_request: false,
+            keymaps: Keymaps::default(),
+            file_picker_request: None,
+            message: None,
+            filter_job: None,
+            swap_written: Instant::now(),
+            swap_hash: 0,
+            quit_requested: false,
+            watcher: None,
+            session_request: None,
+            arg_list: Vec::new(),
+            arg_index: 0,
+            arg_read_only: false,
+        }
+    }
+
+    /// Shows `msg` in the modeline until the next key press.
+    pub fn display_message(&mut self, msg: String) {
+        println!("Display Message! {} ", msg);
+        self.message = Some(msg);
+    }
+
+    /// Reads the config file again, keeping the old config if it can't be read.
+    pub fn source_config(&mut self) {
+        let path = match &self.config_path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        match Config::read_from_file(&path) {
+            Ok((config, message)) => {
+                let profile = config.options_for("").keymap;
+                let (keymaps, errors) = Keymaps::new(&profile, &config.keymaps);
+                self.config = config;
+                self.keymaps = keymaps;
+                let errors = message.into_iter().chain(errors).collect::<Vec<_>>();
+                if !errors.is_empty() {
+                    self.display_message(errors.join(", "));
+                }
+            }
+            Err(msg) => self.display_message(msg),
         }
     }
 
     pub fn reload_color_scheme(&mut self) {
         println!("Reloading color scheme {:?}", &self.cs_path);
-        match &self.cs_path {
-            Some(scheme_path) => match ColorScheme::read_from_file(&scheme_path) {
-                Ok(color_scheme) => self.cs = color_scheme,
-                Err(msg) => display_message(msg),
-            },
+        match self.cs_path.clone() {
+            Some(scheme_path) => self.set_color_scheme(scheme_path),
             None => println!("Color scheme not loaded from file"),
         }
     }
 
     pub fn set_color_scheme(&mut self, filepath: String) {
         match ColorScheme::read_from_file(&filepath) {
-            Ok(color_scheme) => {
+            Ok((color_scheme, warnings)) => {
                 self.cs = color_scheme;
                 self.cs_path = Some(filepath);
+                if !warnings.is_empty() {
+                    self.display_message(warnings.join(", "));
+                }
             }
-            Err(msg) => display_message(msg),
+            Err(msg) => self.display_message(msg),
+        }
+    }
+}
+
+/// Prints the problems of every color scheme, returning the exit code.
+fn check_themes(themes: Vec<String>) -> i32 {
+    let themes = match themes.is_empty() {
+        true => assets::list_themes(),
+        false => themes,
+    };
+    let mut failed = false;
+    for theme in themes {
+        let problems = match ColorScheme::read_from_file(&theme) {
+            Ok((_, warnings)) => warnings,
+            Err(msg) => vec![msg],
+        };
+        match problems.is_empty() {
+            true => println!("{}: ok", theme),
+            false => problems.iter().for_each(|p| println!("{}", p)),
         }
+        failed |= !problems.is_empty();
     }
+    failed as i32
 }
 
 pub fn main() {
+    let args = match cli::parse(std::env::args().skip(1)) {
+        Ok(Cli::Run(args)) => args,
+        Ok(Cli::Exit(text)) => return println!("{}", text),
+        Ok(Cli::CheckThemes(themes)) => std::process::exit(check_themes(themes)),
+        Ok(Cli::ImportTheme(file, name)) => {
+            match theme_import::import_file(&file, name.as_deref()) {
+                Ok((path, unmapped)) => {
+                    unmapped
+                        .iter()
+                        .for_each(|key| println!("not mapped: {}", key));
+                    println!("Imported {} to {}", file, path.display());
+                }
+                Err(msg) => {
+                    eprintln!("rslush: {}", msg);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Err(msg) => {
+            eprintln!("rslush: {}\nTry 'rslush --help' for more information.", msg);
+            std::process::exit(2);
+        }
+    };
+    recovery::install_hook();
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
-    let window = video_subsystem
-        .window("Slush", 800, 600)
+    let mut app = App::new();
+    app.config_path = config::config_path(args.config.as_deref());
+    app.source_config();
+    app.options = app.config.options_for("");
+    let session = match session::find(app.options.autosession).map(|path| session::load(&path)) {
+        Some(Ok(session)) => session,
+        Some(Err(msg)) => {
+            app.display_message(msg);
+            Session::default()
+        }
+        None => Session::default(),
+    };
+
+    let mut window = video_subsystem
+        .window("Slush", app.config.window_size.0, app.config.window_size.1)
         .position_centered()
         .resizable()
         .build()
         .unwrap();
+    video_subsystem.text_input().start();
+    if let Some((width, height)) = session.window_size {
+        let _ = window.set_size(width, height);
+    }
+    if let Some((x, y)) = session.window_position {
+        window.set_position(WindowPos::Positioned(x), WindowPos::Positioned(y));
+    }
 
-    let file_name = "./src/example.kis";
-    let file_text = file_assist::open_file(file_name);
+    let color_scheme = (args.theme.clone())
+        .or_else(|| session.color_scheme.clone())
+        .or_else(|| app.config.color_scheme.clone());
+    app.set_color_scheme(color_scheme.unwrap_or_else(|| String::from(assets::DEFAULT_THEME)));
 
-    let mut app = App::new();
-    app.set_color_scheme(String::from("./colors/oceanic.json"));
-
-    let mut buffer: Buffer = Buffer::new(file_text, file_name.to_string());
+    let mut buffer: Buffer = Buffer::new(String::new(), String::new());
+    let mut display_from: usize = 0;
+    app.arg_read_only = args.read_only;
+    if args.diff {
+        show_diff(
+            &mut buffer,
+            &args.files[0].path,
+            &args.files[1].path,
+            &mut app,
+        );
+    } else if args.stdin {
+        read_stdin(&mut buffer, &mut app);
+    } else if !args.files.is_empty() {
+        app.arg_list = args.files;
+        open_arg(&mut buffer, 0, &mut app);
+    } else if let Some(current) = session.current_buffer() {
+        app.arg_list = session
+            .buffers
+            .iter()
+            .map(|b| FileArg {
+                path: b.file.clone(),
+                row: Some(b.row),
+                col: Some(b.col),
+            })
+            .collect();
+        app.arg_index = session.current;
+        open_file(&mut buffer, &current.file, &mut app);
+        buffer.set_cursor_pos(Pos::new(current.row, current.col));
+        display_from = usize::min(current.scroll, buffer.lines.len() - 1);
+    }
+    if args.read_only {
+        buffer.read_only = true;
+    }
+    if !app.item_picker.is_active() && !recovery::list().is_empty() {
+        show_recovered(&mut app);
+    }
 
     let mut fp_action: FilePickerAction = FilePickerAction::OpenFile;
 
@@ -133,23 +447,29 @@ pub fn main() {
     let mut fps_tick: u32 = 0;
     let mut fps_draw: String = String::from("?");
 
-    let mut display_from: usize = 0;
-
-    let mut file_explorer = FilePicker::new(".");
+    let picker_directory = session.picker_directory.filter(|d| Path::new(d).is_dir());
+    let mut file_explorer = FilePicker::new(picker_directory.as_deref().unwrap_or("."));
 
     let mut input_mode: InputMode = InputMode::Normal;
+    let mut pending_keys = String::new();
+    let mut visual_start = buffer.cursor;
+    let mut replaced: Vec<Option<char>> = Vec::new();
+    // text being composed in an input method, shown at the cursor until committed
+    let mut preedit = String::new();
+    let mut input_rect = Rect::new(0, 0, 1, 1);
 
     let mut canvas = window.into_canvas().build().unwrap();
+    // translucent colors of a scheme, e.g. the selection, blend with what is below
+    canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
     let texture_creator: sdl2::render::TextureCreator<sdl2::video::WindowContext> =
         canvas.texture_creator();
 
     let ttf_context = sdl2::ttf::init().unwrap();
-    let mut dejavu = ttf_context.load_font("./src/Roboto.ttf", 13).unwrap();
-    let (char_size_x, char_size_y) = dejavu.size_of_char('a').unwrap();
-
-    dejavu.set_style(sdl2::ttf::FontStyle::NORMAL);
-    dejavu.set_hinting(sdl2::ttf::Hinting::Mono);
-    dejavu.set_kerning(true);
+    let mut dejavu = match load_font(&ttf_context, &mut app) {
+        Ok(font) => font,
+        Err(msg) => return eprintln!("Unable to load the font: {}", msg),
+    };
+    let (mut char_size_x, mut char_size_y) = dejavu.size_of_char('a').unwrap();
 
     canvas.set_draw_color(Color::RGB(0, 255, 255));
     canvas.clear();
@@ -163,221 +483,417 @@ pub fn main() {
             second_now = tnow;
             fps_tick = 0;
         }
+        if buffer.modified && app.swap_written.elapsed() >= SWAP_INTERVAL {
+            // copying every line is too slow for each key, crash dumps lag like the swap file
+            recovery::track(&[&buffer]);
+            app.swap_written = Instant::now();
+            if !buffer.read_only {
+                write_swap(&buffer, &mut app);
+            }
+        }
+        let mut edited = false;
+        if app.watcher.as_mut().is_some_and(|w| w.changed()) {
+            external_change(&mut buffer, &mut app);
+            edited = true;
+        }
+        if let Some(result) = app.filter_job.as_mut().and_then(|job| job.poll()) {
+            let job = app.filter_job.take().unwrap();
+            finish_filter(job, result, &mut buffer, &mut app);
+            edited = true;
+        }
+
         let (windowx, windowy) = canvas.window().size();
-        canvas.set_draw_color(app.cs.buffer_bg);
+        canvas.set_draw_color(app.cs.style("Normal").bg);
         canvas.clear();
+        let mut typed: VecDeque<Typed> = VecDeque::new();
         for event in event_pump.poll_iter() {
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
+                Event::Quit { .. } if confirm_quit(&buffer, &mut app) => break 'running,
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } if app.options.autosave && buffer.modified && !buffer.read_only => {
+                    save_buffer(&mut buffer, &mut app, false)
+                }
+                Event::KeyDown {
                     keycode: Some(Keycode::Backquote),
+                    keymod,
                     ..
-                } => break 'running,
+                } if !keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD)
+                    && confirm_quit(&buffer, &mut app) =>
+                {
+                    break 'running
+                }
                 Event::KeyDown {
-                    keycode, keymod, ..
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
                 } => {
                     let ctrl = keymod.intersects(Mod::LCTRLMOD) || keymod.intersects(Mod::RCTRLMOD);
-                    let shift =
-                        keymod.intersects(Mod::LSHIFTMOD) || keymod.intersects(Mod::RSHIFTMOD);
-                    // println!("Pressed {:?} ctrl:{} shift:{}", keycode, ctrl, shift);
-                    if ctrl && keycode == Some(Keycode::O) {
-                        file_explorer.activate();
-                        fp_action = FilePickerAction::OpenFile;
-                    } else if ctrl && keycode == Some(Keycode::P) {
-                        file_explorer.activate();
-                        fp_action = FilePickerAction::ChangeColorScheme;
-                    } else if ctrl && keycode == Some(Keycode::B) {
-                        app.reload_color_scheme();
-                    } else if ctrl && keycode == Some(Keycode::N) {
-                        // change color scheme
-                        let schemes = file_assist::list_color_schemes();
-                        let items = schemes
-                            .iter()
-                            .enumerate()
-                            .map(|(i, s)| Item::new(i, s.clone()))
-                            .collect();
-                        app.item_picker.activate(items);
-                        app.item_action = Some(ChangeColorScheme(schemes));
-                    } else if ctrl && keycode == Some(Keycode::M) {
-                        // execute command
-                        app.item_picker.activate(vec![]);
-                        app.item_action = Some(ExecuteCommand);
-                    } else {
+                    if ctrl && keycode == Keycode::C && app.filter_job.is_some() {
+                        if let Some(mut job) = app.filter_job.take() {
+                            job.cancel();
+                            app.display_message(format!("Cancelled {}", job.command));
+                        }
+                    } else if let Some(key) = sdl_key(keycode, ctrl) {
+                        typed.push_back(Typed::Key(key));
+                    }
+                }
+                // typed characters, whatever the keyboard layout
+                Event::TextInput { text, .. } if !preedit.is_empty() => {
+                    preedit.clear();
+                    typed.push_back(Typed::Composed(text));
+                }
+                Event::TextInput { text, .. } => {
+                    typed.extend(text.chars().map(|c| Typed::Key(Key::Char(c))))
+                }
+                Event::TextEditing { text, .. } => preedit = text,
+                _ => {}
+            }
+        }
+        let mut flushed = false;
+        loop {
+            // keys are mapped one at a time, in the mode left by the previous one
+            let steps = match typed.pop_front() {
+                Some(Typed::Composed(text))
+                    if (input_mode == InputMode::Insert || input_mode == InputMode::Replace)
+                        && keymap_mode(input_mode, &file_explorer, &mut app)
+                            != keymap::Mode::Picker =>
+                {
+                    app.message = None;
+                    edited = true;
+                    insert_composed(&text, input_mode, &mut buffer, &mut replaced);
+                    continue;
+                }
+                // commands and picker filters take composed text like typed keys
+                Some(Typed::Composed(text)) => {
+                    for c in text.chars().rev() {
+                        typed.push_front(Typed::Key(Key::Char(c)));
+                    }
+                    continue;
+                }
+                Some(Typed::Key(key)) => {
+                    let mode = keymap_mode(input_mode, &file_explorer, &mut app);
+                    app.keymaps.feed(mode, key, Instant::now())
+                }
+                None if !flushed => {
+                    flushed = true;
+                    let timeout = Duration::from_millis(app.options.timeoutlen as u64);
+                    app.keymaps.flush(Instant::now(), timeout)
+                }
+                None => break,
+            };
+            for step in steps {
+                app.message = None;
+                app.quit_requested = false;
+                edited = true;
+                if input_mode == InputMode::Normal {
+                    buffer.begin_change();
+                }
+                match step {
+                    Step::Ex(command) => execute_command(&command, &mut buffer, &mut app),
+                    Step::Key(key) => {
                         if file_explorer.is_active() {
-                            if ctrl {
-                                match keycode {
-                                    Some(Keycode::J) => file_explorer.selection_down(),
-                                    Some(Keycode::K) => file_explorer.selection_up(),
-                                    Some(Keycode::H) => file_explorer.delete_segment(),
-                                    Some(Keycode::L) => {
-                                        if let Some(filename) = file_explorer.confirm_selection() {
-                                            file_explorer.deactivate();
-                                            match fp_action {
-                                                FilePickerAction::OpenFile => {
-                                                    open_file(&mut buffer, &filename)
-                                                }
-                                                FilePickerAction::ChangeColorScheme => {
-                                                    app.set_color_scheme(filename)
-                                                }
+                            match key {
+                                Key::Ctrl('j') | Key::Down => file_explorer.selection_down(),
+                                Key::Ctrl('k') | Key::Up => file_explorer.selection_up(),
+                                Key::Ctrl('h') => file_explorer.delete_segment(),
+                                Key::Ctrl('l') | Key::Enter => {
+                                    if let Some(filename) = file_explorer.confirm_selection() {
+                                        file_explorer.deactivate();
+                                        match fp_action {
+                                            FilePickerAction::OpenFile if buffer.modified => app
+                                                .display_message(String::from(
+                                                    "No write since last change, save before opening another file",
+                                                )),
+                                            FilePickerAction::OpenFile => {
+                                                open_file(&mut buffer, &filename, &mut app);
                                             }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            } else {
-                                match keycode {
-                                    Some(Keycode::Q) => file_explorer.insert_character("q"),
-                                    Some(Keycode::W) => file_explorer.insert_character("w"),
-                                    Some(Keycode::E) => file_explorer.insert_character("e"),
-                                    Some(Keycode::R) => file_explorer.insert_character("r"),
-                                    Some(Keycode::T) => file_explorer.insert_character("t"),
-                                    Some(Keycode::Y) => file_explorer.insert_character("y"),
-                                    Some(Keycode::U) => file_explorer.insert_character("u"),
-                                    Some(Keycode::I) => file_explorer.insert_character("i"),
-                                    Some(Keycode::O) => file_explorer.insert_character("o"),
-                                    Some(Keycode::P) => file_explorer.insert_character("p"),
-                                    // middle row
-                                    Some(Keycode::A) => file_explorer.insert_character("a"),
-                                    Some(Keycode::S) => file_explorer.insert_character("s"),
-                                    Some(Keycode::D) => file_explorer.insert_character("d"),
-                                    Some(Keycode::F) => file_explorer.insert_character("f"),
-                                    Some(Keycode::G) => file_explorer.insert_character("g"),
-                                    Some(Keycode::H) => file_explorer.insert_character("h"),
-                                    Some(Keycode::J) => file_explorer.insert_character("j"),
-                                    Some(Keycode::K) => file_explorer.insert_character("k"),
-                                    Some(Keycode::L) => file_explorer.insert_character("l"),
-                                    // bottom row
-                                    Some(Keycode::Z) => file_explorer.insert_character("z"),
-                                    Some(Keycode::X) => file_explorer.insert_character("x"),
-                                    Some(Keycode::C) => file_explorer.insert_character("c"),
-                                    Some(Keycode::V) => file_explorer.insert_character("v"),
-                                    Some(Keycode::B) => file_explorer.insert_character("b"),
-                                    Some(Keycode::N) => file_explorer.insert_character("n"),
-                                    Some(Keycode::M) => file_explorer.insert_character("m"),
-
-                                    Some(Keycode::Period) => file_explorer.insert_character("."),
-
-                                    Some(Keycode::Up) => file_explorer.selection_up(),
-                                    Some(Keycode::Down) => file_explorer.selection_down(),
-
-                                    Some(Keycode::Return) => {
-                                        if let Some(filename) = file_explorer.confirm_selection() {
-                                            file_explorer.deactivate();
-                                            match fp_action {
-                                                FilePickerAction::OpenFile => {
-                                                    open_file(&mut buffer, &filename)
-                                                }
-                                                FilePickerAction::ChangeColorScheme => {
-                                                    app.set_color_scheme(filename)
-                                                }
+                                            FilePickerAction::ChangeColorScheme => {
+                                                app.set_color_scheme(filename)
                                             }
                                         }
                                     }
-                                    Some(Keycode::Backspace) => file_explorer.delete_character(),
-                                    Some(Keycode::Escape) => file_explorer.quit(),
-                                    _ => (),
                                 }
+                                Key::Backspace => file_explorer.delete_character(),
+                                Key::Escape => file_explorer.quit(),
+                                Key::Char(c) => file_explorer.insert_character(&c.to_string()),
+                                _ => (),
                             }
                         } else if app.item_picker.is_active() {
-                            if let Some(key) = &keycode {
-                                app.item_picker.try_handle_key(key, shift, ctrl);
-                                if let Some(item) = app.item_picker.get_accepted() {
-                                    item_picker_action(item, &mut app);
-                                    app.item_picker.deactivate();
-                                }
+                            app.item_picker.try_handle_key(key);
+                            if let Some(item) = app.item_picker.get_accepted() {
+                                app.item_picker.deactivate();
+                                item_picker_action(item, &mut buffer, &mut app);
+                            } else if !app.item_picker.is_active() {
+                                item_picker_cancel(&mut app);
+                            } else {
+                                preview_color_scheme(&mut app);
                             }
                         } else if input_mode == InputMode::Insert {
-                            if keycode == Some(Keycode::Escape) {
+                            if key == Key::Escape {
                                 input_mode = InputMode::Normal;
                                 buffer.move_cursor(Direction::Left);
+                            } else {
+                                handle_key_ins_mode(key, &mut buffer);
                             }
-                            match keycode {
-                                Some(k) => handle_key_ins_mode(k, shift, &mut buffer),
-                                _ => (),
+                        } else if input_mode == InputMode::Replace {
+                            if key == Key::Escape {
+                                input_mode = InputMode::Normal;
+                                replaced.clear();
+                                buffer.move_cursor(Direction::Left);
+                            } else {
+                                handle_key_replace_mode(key, &mut buffer, &mut replaced);
                             }
-                        } else {
-                            //NormalMode
-                            match keycode {
-                                Some(Keycode::H) => buffer.move_cursor(Direction::Left),
-                                Some(Keycode::L) => buffer.move_cursor(Direction::Right),
-                                Some(Keycode::J) => buffer.move_cursor(Direction::Down),
-                                Some(Keycode::K) => buffer.move_cursor(Direction::Up),
-
-                                Some(Keycode::D) => buffer.delete_line(),
-                                Some(Keycode::O) => {
-                                    buffer.insert_newline_below();
-                                    buffer.move_cursor(Direction::Down);
-                                    input_mode = InputMode::Insert;
+                        } else if input_mode == InputMode::Command {
+                            match key {
+                                Key::Escape => input_mode = InputMode::Normal,
+                                Key::Enter => {
+                                    input_mode = InputMode::Normal;
+                                    let line = app.command_line.clone();
+                                    execute_command(&line, &mut buffer, &mut app);
+                                    app.visual_marks = None;
                                 }
-                                Some(Keycode::S) => {
-                                    file_assist::save_file(&buffer.file_name, &buffer.lines);
-                                    buffer.saved()
+                                Key::Backspace if app.command_line.is_empty() => {
+                                    input_mode = InputMode::Normal
                                 }
-
-                                Some(Keycode::I) => input_mode = InputMode::Insert,
-                                Some(Keycode::A) => {
-                                    input_mode = InputMode::Insert;
-                                    buffer.move_cursor(Direction::Right);
-                                }
-                                Some(Keycode::X) => {
-                                    buffer.delete_current_character();
+                                Key::Backspace => {
+                                    app.command_line.pop();
                                 }
+                                Key::Char(c) => app.command_line.push(c),
                                 _ => (),
                             }
+                        } else if input_mode == InputMode::Visual {
+                            if key == Key::Escape {
+                                pending_keys.clear();
+                                input_mode = InputMode::Normal;
+                            } else if let Some(c) = key.to_char() {
+                                pending_keys.push(c);
+                                match normal::parse_visual(&pending_keys) {
+                                    Parsed::Pending => (),
+                                    Parsed::Invalid => pending_keys.clear(),
+                                    Parsed::Done(action) => {
+                                        pending_keys.clear();
+                                        handle_visual_action(
+                                            action,
+                                            &mut buffer,
+                                            &mut input_mode,
+                                            visual_start,
+                                            &mut app,
+                                        );
+                                    }
+                                }
+                            }
+                        } else {
+                            //NormalMode
+                            if key == Key::Escape {
+                                pending_keys.clear();
+                            } else if let Some(c) = key.to_char() {
+                                pending_keys.push(c);
+                                match normal::parse(&pending_keys) {
+                                    Parsed::Pending => (),
+                                    Parsed::Invalid => pending_keys.clear(),
+                                    Parsed::Done(command) => {
+                                        pending_keys.clear();
+                                        handle_normal_action(
+                                            command,
+                                            &mut buffer,
+                                            &mut input_mode,
+                                            &mut visual_start,
+                                            &mut app,
+                                        );
+                                    }
+                                }
+                            }
                         }
                     }
                 }
-                _ => {}
+                if input_mode == InputMode::Normal {
+                    buffer.end_change();
+                }
+            }
+        }
+        if let Some(action) = app.file_picker_request.take() {
+            file_explorer.activate();
+            fp_action = action;
+        }
+        if edited && !buffer.modified {
+            // forgets the lines tracked before the buffer was saved
+            recovery::track(&[&buffer]);
+        }
+        if app.font_request {
+            app.font_request = false;
+            match load_font(&ttf_context, &mut app) {
+                Ok(font) => {
+                    dejavu = font;
+                    let (x, y) = dejavu.size_of_char('a').unwrap();
+                    char_size_x = x;
+                    char_size_y = y;
+                }
+                Err(msg) => app.display_message(msg),
+            }
+        }
+        if let Some(path) = app.session_request.take() {
+            let session =
+                current_session(&buffer, display_from, &file_explorer, canvas.window(), &app);
+            match session::save(&path, &session) {
+                Ok(()) => app.display_message(format!("Session written to {}", path.display())),
+                Err(msg) => app.display_message(msg),
             }
         }
 
         let rows_displayed: usize = ((windowy - char_size_y) / char_size_y) as usize;
-        let display_to = usize::min(display_from + rows_displayed, buffer.lines.len());
-        let mut i: u32 = 0;
-        for l in &buffer.lines[display_from..display_to] {
-            let lne = format!("{:3}|{}", i + display_from as u32, l);
-            let rendering = dejavu.render(&lne);
-            let surface = rendering.blended(app.cs.buffer_fg).unwrap();
-            let texture = surface.as_texture(&texture_creator).unwrap();
-            canvas
-                .copy(
-                    &texture,
-                    None,
-                    Rect::new(
-                        STARTX as i32,
-                        (STARTY + surface.height() * i) as i32,
-                        surface.width(),
-                        surface.height(),
-                    ),
-                )
-                .unwrap();
-            i += 1;
+        let gutter: u32 = if app.options.number { 4 } else { 0 };
+        let text_cols = usize::max(1, (windowx / char_size_x).saturating_sub(gutter) as usize);
+        let layout = Layout {
+            tabstop: app.options.tabstop,
+            wrap_width: Some(text_cols).filter(|_| app.options.wrap),
+        };
+        display_from = layout::scroll(
+            &buffer.lines,
+            display_from,
+            buffer.cursor.row as usize,
+            rows_displayed,
+            app.options.scrolloff,
+            &layout,
+        );
+        // screen row of every displayed line
+        let mut line_rows: Vec<usize> = Vec::new();
+        let mut display_to = display_from;
+        let mut screen_row = 0;
+        while display_to < buffer.lines.len() && screen_row < rows_displayed {
+            line_rows.push(screen_row);
+            screen_row += layout.rows(&buffer.lines[display_to]);
+            display_to += 1;
+        }
+        if input_mode == InputMode::Visual {
+            let selection = visual_range(&buffer, visual_start);
+            canvas.set_draw_color(app.cs.style("Visual").bg);
+            for row in usize::max(selection.start.row, display_from)
+                ..usize::min(selection.end.row + 1, display_to)
+            {
+                let line = &buffer.lines[row];
+                let from_col = if row == selection.start.row {
+                    selection.start.col
+                } else {
+                    0
+                };
+                let to_col = if row == selection.end.row {
+                    selection.end.col
+                } else {
+                    line.len() + 1
+                };
+                let (first_row, first_cell) = layout.position(line, from_col);
+                let (last_row, last_cell) = layout.position(line, to_col);
+                for r in first_row..=last_row {
+                    let from_cell = if r == first_row { first_cell } else { 0 };
+                    let to_cell = if r == last_row { last_cell } else { text_cols };
+                    if to_cell <= from_cell {
+                        continue;
+                    }
+                    let y = line_rows[row - display_from] + r;
+                    canvas
+                        .fill_rect(Rect::new(
+                            (char_size_x * (from_cell as u32 + gutter)) as i32,
+                            (char_size_y * y as u32) as i32,
+                            char_size_x * (to_cell - from_cell) as u32,
+                            char_size_y,
+                        ))
+                        .unwrap();
+                }
+            }
         }
 
-        if (buffer.cursor.row as usize) < display_from {
-            display_from = buffer.cursor.row as usize;
+        let normal = app.cs.style("Normal");
+        let line_nr = app.cs.style("LineNr");
+        if gutter > 0 {
+            canvas.set_draw_color(line_nr.bg);
+            canvas
+                .fill_rect(Rect::new(
+                    STARTX as i32,
+                    STARTY as i32,
+                    char_size_x * gutter,
+                    char_size_y * rows_displayed as u32,
+                ))
+                .unwrap();
         }
-        if buffer.cursor.row as usize > display_to - 1
-            && buffer.cursor.row < buffer.lines.len() as u32 - 1
-        {
-            display_from += (buffer.cursor.row as usize - (display_to - 1)) as usize;
+        for (i, l) in buffer.lines[display_from..display_to].iter().enumerate() {
+            for (j, segment) in layout.segments(l).iter().enumerate() {
+                let y = line_rows[i] + j;
+                if y >= rows_displayed {
+                    continue;
+                }
+                let mut parts = vec![(gutter, segment.clone(), &normal)];
+                if gutter > 0 && j == 0 {
+                    parts.push((0, format!("{:3}|", i + display_from), &line_nr));
+                }
+                for (cell, text, style) in parts {
+                    if text.is_empty() {
+                        continue;
+                    }
+                    let surface = render_styled(&mut dejavu, &text, style);
+                    let texture = surface.as_texture(&texture_creator).unwrap();
+                    canvas
+                        .copy(
+                            &texture,
+                            None,
+                            Rect::new(
+                                (STARTX + char_size_x * cell) as i32,
+                                (STARTY + char_size_y * y as u32) as i32,
+                                surface.width(),
+                                surface.height(),
+                            ),
+                        )
+                        .unwrap();
+                }
+            }
         }
 
-        canvas.set_draw_color(app.cs.cursor);
+        canvas.set_draw_color(app.cs.style("Cursor").bg);
+        let cursor_line = &buffer.lines[buffer.cursor.row as usize];
+        let (cursor_row, cursor_cell) = layout.position(cursor_line, buffer.cursor.col as usize);
+        let cursor_y = line_rows
+            .get(buffer.cursor.row as usize - display_from)
+            .unwrap_or(&0);
         let from = (
-            (char_size_x * buffer.cursor.col) as i32 + 4 * char_size_x as i32,
-            (char_size_y * buffer.cursor.row - char_size_y * display_from as u32) as i32,
+            (char_size_x * (cursor_cell as u32 + gutter)) as i32,
+            (char_size_y * (cursor_y + cursor_row) as u32) as i32,
         );
         if input_mode == InputMode::Insert {
             let to = (from.0, from.1 + char_size_y as i32);
             canvas.draw_line(from, to).unwrap();
+        } else if input_mode == InputMode::Replace {
+            let y = from.1 + char_size_y as i32 - 1;
+            canvas
+                .draw_line((from.0, y), (from.0 + char_size_x as i32, y))
+                .unwrap();
         } else {
             let rct = Rect::new(from.0, from.1, char_size_x, char_size_y);
             canvas.draw_rect(rct).unwrap();
         }
+        // candidate window of the input method next to the cursor
+        let cursor_rect = Rect::new(from.0, from.1, char_size_x, char_size_y);
+        if cursor_rect != input_rect {
+            input_rect = cursor_rect;
+            video_subsystem.text_input().set_rect(input_rect);
+        }
+        if !preedit.is_empty() && input_mode != InputMode::Command {
+            let surface = dejavu.render(&preedit).blended(normal.fg).unwrap();
+            let texture = surface.as_texture(&texture_creator).unwrap();
+            let rect = Rect::new(from.0, from.1, surface.width(), surface.height());
+            canvas.set_draw_color(normal.bg);
+            canvas.fill_rect(rect).unwrap();
+            canvas.copy(&texture, None, rect).unwrap();
+            let y = from.1 + char_size_y as i32 - 1;
+            canvas.set_draw_color(normal.fg);
+            canvas
+                .draw_line((from.0, y), (from.0 + surface.width() as i32, y))
+                .unwrap();
+        }
 
         // draw modeline
-        canvas.set_draw_color(app.cs.statusline_bg);
+        let status_line = app.cs.style("StatusLine");
+        canvas.set_draw_color(status_line.bg);
         let modeline_from = (0, (windowy - char_size_y) as i32);
         let modeline_to = (windowx as i32, (windowy - char_size_y) as i32);
         canvas.draw_line(modeline_from, modeline_to).unwrap();
@@ -389,11 +905,16 @@ pub fn main() {
                 char_size_y,
             ))
             .unwrap();
-        let mut txt = String::from(if input_mode == InputMode::Insert {
-            " INSERT"
-        } else {
-            " NORMAL"
+        let mut txt = String::from(match input_mode {
+            InputMode::Insert => " INSERT",
+            InputMode::Normal => " NORMAL",
+            InputMode::Visual => " VISUAL",
+            InputMode::Replace => " REPLACE",
+            InputMode::Command => " COMMAND",
         });
+        if input_mode == InputMode::Command {
+            txt = format!(":{}{}", app.command_line, preedit);
+        }
         txt += " | ";
         txt += &format!(
             "{}:{} ({:2}%)",
@@ -404,9 +925,30 @@ pub fn main() {
         if buffer.modified {
             txt += " *M* ";
         }
-        txt += &format!(" [{}] ", buffer.file_name);
-        let rendering = dejavu.render(&txt);
-        let surface = rendering.blended(app.cs.statusline_fg).unwrap();
+        if buffer.read_only {
+            txt += " [RO] ";
+        }
+        txt += &format!(" [{}] ", buffer.format.encoding.name());
+        if buffer.format.line_ending != LineEnding::Unix {
+            txt += &format!(" [{}] ", buffer.format.line_ending.name());
+        }
+        if !buffer.format.final_newline {
+            txt += " [noeol] ";
+        }
+        match buffer.file_name.as_str() {
+            "" => txt += " [No Name] ",
+            name => txt += &format!(" [{}] ", name),
+        }
+
+        txt += &app.keymaps.pending();
+        txt += &pending_keys;
+        if let Some(job) = &app.filter_job {
+            txt += &format!(" [!{}] ", job.command);
+        }
+        if let Some(msg) = &app.message {
+            txt += &format!(" {}", msg);
+        }
+        let surface = render_styled(&mut dejavu, &txt, &status_line);
         let texture = surface.as_texture(&texture_creator).unwrap();
         canvas
             .copy(
@@ -421,8 +963,7 @@ pub fn main() {
             )
             .unwrap();
 
-        let rendering = dejavu.render(&fps_draw);
-        let surface = rendering.blended(app.cs.statusline_fg).unwrap();
+        let surface = render_styled(&mut dejavu, &fps_draw, &status_line);
         let texture = surface.as_texture(&texture_creator).unwrap();
         canvas
             .copy(
@@ -448,9 +989,10 @@ pub fn main() {
             let items_count = items_space_y / char_size_y;
 
             // draw area
-            canvas.set_draw_color(app.cs.filepicker_bg);
+            let picker = app.cs.style("FilePicker");
+            canvas.set_draw_color(picker.bg);
             canvas.fill_rect(draw_area).unwrap();
-            canvas.set_draw_color(app.cs.filepicker_border);
+            canvas.set_draw_color(app.cs.style("FilePicker.Border").fg);
             canvas.draw_rect(draw_area).unwrap();
 
             // draw prompt
@@ -463,7 +1005,7 @@ pub fn main() {
                 file_explorer.inserted_part()
             );
             let rendering = dejavu.render(&prompt);
-            let surface = rendering.blended(app.cs.filepicker_fg).unwrap();
+            let surface = rendering.blended(picker.fg).unwrap();
             let texture = surface.as_texture(&texture_creator).unwrap();
             let mut r = Rect::new(
                 draw_area.x(),
@@ -481,13 +1023,13 @@ pub fn main() {
             let mut id = 0;
             for e in file_explorer.get_items(items_count as usize) {
                 if id == file_explorer.selected_line() {
-                    canvas.set_draw_color(app.cs.filepicker_selection);
+                    canvas.set_draw_color(app.cs.style("FilePicker.Selection").bg);
                     canvas
                         .fill_rect(Rect::new(10, i, draw_area.width(), char_size_y))
                         .unwrap();
                 }
                 let rendering = dejavu.render(e.name.as_str());
-                let surface = rendering.blended(app.cs.filepicker_fg).unwrap();
+                let surface = rendering.blended(picker.fg).unwrap();
                 let texture = surface.as_texture(&texture_creator).unwrap();
                 let r = Rect::new(15, i, surface.width(), surface.height());
                 canvas.copy(&texture, None, r).unwrap();
@@ -518,95 +1060,915 @@ pub fn main() {
         canvas.present();
         ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
     }
+    // quitting with unsaved changes was confirmed, they are discarded on purpose
+    swap::remove(&buffer.file_name);
+    if let Some(path) = session::auto_path().filter(|_| app.options.autosession) {
+        let session = current_session(&buffer, display_from, &file_explorer, canvas.window(), &app);
+        // the window is gone, the modeline can't show it
+        if let Err(msg) = session::save(&path, &session) {
+            eprintln!("rslush: {}", msg);
+        }
+    }
 }
 
-fn open_file(buffer: &mut Buffer, filename: &String) {
-    buffer.update(file_assist::open_file(&filename), filename);
+/// Whether to quit: right away without unsaved changes, otherwise when asked twice in a row.
+fn confirm_quit(buffer: &Buffer, app: &mut App) -> bool {
+    if !buffer.modified || app.quit_requested {
+        return true;
+    }
+    app.quit_requested = true;
+    app.display_message(String::from(
+        "No write since last change, quit again to discard it",
+    ));
+    false
 }
 
-fn display_message(msg: String) {
-    println!("Display Message! {} ", msg);
+fn current_session(
+    buffer: &Buffer,
+    display_from: usize,
+    file_explorer: &FilePicker,
+    window: &Window,
+    app: &App,
+) -> Session {
+    let pos = buffer.cursor_pos();
+    let directory = &file_explorer.current_directory.path;
+    let shown = BufferSession {
+        file: buffer.file_name.clone(),
+        row: pos.row,
+        col: pos.col,
+        scroll: display_from,
+    };
+    let args = app
+        .arg_list
+        .iter()
+        .map(|arg| BufferSession {
+            file: arg.path.clone(),
+            row: arg.row.unwrap_or(0),
+            col: arg.col.unwrap_or(0),
+            scroll: 0,
+        })
+        .collect();
+    let mut session = Session {
+        window_size: Some(window.size()),
+        window_position: Some(window.position()),
+        picker_directory: Some(directory.to_string_lossy().to_string()),
+        color_scheme: app.cs_path.clone(),
+        ..Session::default()
+    };
+    session.set_buffers(args, app.arg_index, shown);
+    session
+}
+
+/// `:map lhs rhs`, `:unmap lhs` and their mode variants; `:map` alone lists mappings.
+fn map_command(modes: &[keymap::Mode], unmap: bool, args: &str, app: &mut App) {
+    let args = args.trim();
+    if args.is_empty() && !unmap {
+        let items = app
+            .keymaps
+            .list()
+            .into_iter()
+            .enumerate()
+            .map(|(i, m)| Item::new(i, m))
+            .collect();
+        app.item_picker.activate(items);
+        app.item_action = Some(ExecuteCommand);
+        return;
+    }
+    let result = match args.split_once(char::is_whitespace) {
+        _ if unmap => {
+            let results: Vec<Result<(), String>> =
+                modes.iter().map(|m| app.keymaps.unmap(*m, args)).collect();
+            match results.iter().any(|r| r.is_ok()) {
+                true => Ok(()),
+                false => results.into_iter().next().unwrap(),
+            }
+        }
+        Some((lhs, rhs)) => modes
+            .iter()
+            .try_for_each(|m| app.keymaps.map(*m, lhs, rhs.trim())),
+        None => Err(format!("Expected keys and what to map them to: {}", args)),
+    };
+    if let Err(msg) = result {
+        app.display_message(msg);
+    }
 }
 
-fn shft(no_shift: char, shift: char, is_shift: bool) -> char {
-    if is_shift {
-        shift
+/// Font from the config, or the built-in one when none is set or it can't be loaded.
+fn load_font<'ttf>(
+    ttf_context: &'ttf Sdl2TtfContext,
+    app: &mut App,
+) -> Result<Font<'ttf, 'static>, String> {
+    let size = app.config.font_size;
+    let from_config = app
+        .config
+        .font
+        .as_deref()
+        .map(|font| assets::font_file(font).and_then(|path| ttf_context.load_font(path, size)));
+    let mut font = match from_config {
+        Some(Ok(font)) => font,
+        other => {
+            if let Some(Err(msg)) = other {
+                app.display_message(msg);
+            }
+            let font = RWops::from_bytes(assets::FONT)?;
+            ttf_context.load_font_from_rwops(font, size)?
+        }
+    };
+    font.set_style(FontStyle::NORMAL);
+    font.set_hinting(sdl2::ttf::Hinting::Mono);
+    font.set_kerning(true);
+    Ok(font)
+}
+
+/// Renders `text` in the color and font style of a highlight group.
+fn render_styled(font: &mut Font, text: &str, style: &Style) -> Surface<'static> {
+    let flags = [
+        (style.bold, FontStyle::BOLD),
+        (style.italic, FontStyle::ITALIC),
+        (style.underline, FontStyle::UNDERLINE),
+    ];
+    let font_style = flags
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(FontStyle::NORMAL, |all, (_, flag)| all | *flag);
+    font.set_style(font_style);
+    let surface = font.render(text).blended(style.fg).unwrap();
+    font.set_style(FontStyle::NORMAL);
+    surface
+}
+
+/// Replaces the buffer with the file, or keeps it and shows why the file can't be opened.
+/// Returns whether the file was opened.
+fn open_file(buffer: &mut Buffer, filename: &str, app: &mut App) -> bool {
+    match file_assist::open_file(filename) {
+        Ok(opened) => {
+            // the swap file of unsaved changes stays, see `discard_swap`
+            if !buffer.modified {
+                swap::remove(&buffer.file_name);
+            }
+            app.options = app.config.options_for(filename);
+            buffer.update(opened.text, &filename.to_string());
+            buffer.read_only = opened.read_only;
+            buffer.format = opened.format;
+            if let Some(msg) = opened.message {
+                app.display_message(msg);
+            }
+            app.watcher = Some(FileWatcher::new(filename));
+            load_history(buffer, app);
+            check_swap(buffer, app);
+            true
+        }
+        Err(msg) => {
+            app.display_message(msg);
+            false
+        }
+    }
+}
+
+/// Removes the swap file of `file` once the changes it holds were discarded with `!`.
+fn discard_swap(file: &str, opened: bool, bang: bool) {
+    if opened && bang {
+        swap::remove(file);
+    }
+}
+
+/// Opens the `index`th file of the argument list at its position, returns whether it was opened.
+fn open_arg(buffer: &mut Buffer, index: usize, app: &mut App) -> bool {
+    let arg = app.arg_list[index].clone();
+    app.arg_index = index;
+    if !open_file(buffer, &arg.path, app) {
+        return false;
+    }
+    if app.arg_read_only {
+        buffer.read_only = true;
+    }
+    if let Some(row) = arg.row {
+        // set_cursor_pos clamps cli::LAST_ROW to the last line
+        buffer.set_cursor_pos(Pos::new(row, arg.col.unwrap_or(0)));
+    }
+    true
+}
+
+/// Fills an unnamed buffer from stdin, for `command | rslush -`.
+fn read_stdin(buffer: &mut Buffer, app: &mut App) {
+    match file_assist::read_stdin() {
+        Ok(opened) => {
+            buffer.update(opened.text, &String::new());
+            buffer.format = opened.format;
+            if let Some(msg) = opened.message {
+                app.display_message(msg);
+            }
+        }
+        Err(msg) => app.display_message(msg),
+    }
+}
+
+/// Shows `diff -u` of two files in a read-only buffer, for `rslush -d`.
+fn show_diff(buffer: &mut Buffer, old: &str, new: &str, app: &mut App) {
+    let lines = match file_assist::open_file(new) {
+        Ok(opened) => opened
+            .text
+            .split('\n')
+            .map(String::from)
+            .collect::<Vec<_>>(),
+        Err(msg) => return app.display_message(msg),
+    };
+    match file_assist::diff(old, &lines) {
+        Ok(diff) if diff.is_empty() => {
+            app.display_message(format!("{} and {} are identical", old, new))
+        }
+        Ok(diff) => {
+            buffer.update(diff.join("\n"), &String::new());
+            buffer.read_only = true;
+        }
+        Err(msg) => app.display_message(msg),
+    }
+}
+
+/// Asks what to do with a swap left by a crash, or opens the file read-only when
+/// another rslush is still editing it.
+fn check_swap(buffer: &mut Buffer, app: &mut App) {
+    let swap = match swap::find(&buffer.file_name) {
+        Some(swap) if swap.pid != std::process::id() => swap,
+        _ => return,
+    };
+    if swap::is_running(swap.pid) {
+        buffer.read_only = true;
+        return app.display_message(format!(
+            "{} is being edited by rslush (pid {}), opened read-only",
+            buffer.file_name, swap.pid
+        ));
+    }
+    app.display_message(format!("Found swap file {}", swap.path.display()));
+    let choices = ["Recover", "Open read-only", "Delete swap file"];
+    let items = choices
+        .iter()
+        .enumerate()
+        .map(|(i, c)| Item::new(i, c.to_string()))
+        .collect();
+    app.item_picker.activate(items);
+    app.item_action = Some(RecoverSwap(swap));
+}
+
+/// Rereads the file, keeping the cursor where it was.
+fn reload_buffer(buffer: &mut Buffer, app: &mut App) {
+    let pos = buffer.cursor_pos();
+    match file_assist::open_file(&buffer.file_name) {
+        Ok(opened) => {
+            let file_name = buffer.file_name.clone();
+            buffer.update(opened.text, &file_name);
+            buffer.read_only = opened.read_only;
+            buffer.format = opened.format;
+            buffer.set_cursor_pos(pos);
+            load_history(buffer, app);
+            swap::remove(&file_name);
+            if let Some(watcher) = app.watcher.as_mut() {
+                watcher.reset();
+            }
+        }
+        Err(msg) => app.display_message(msg),
+    }
+}
+
+/// Restores the undo history saved when the file was last written, if it still matches.
+fn load_history(buffer: &mut Buffer, app: &App) {
+    if app.options.undofile {
+        if let Some(history) = undo::load(&buffer.file_name, &buffer.lines) {
+            buffer.history = history;
+        }
+    }
+}
+
+/// Reloads a buffer without local edits, otherwise asks what to do.
+fn external_change(buffer: &mut Buffer, app: &mut App) {
+    if buffer.modified {
+        ask_external_change(buffer, app);
     } else {
-        no_shift
+        reload_buffer(buffer, app);
+        app.display_message(format!(
+            "\"{}\" changed on disk, reloaded",
+            buffer.file_name
+        ));
     }
 }
 
-fn handle_key_ins_mode(keycode: Keycode, shift: bool, buffer: &mut Buffer) {
+fn ask_external_change(buffer: &Buffer, app: &mut App) {
+    app.display_message(format!(
+        "\"{}\" changed on disk and in the buffer",
+        buffer.file_name
+    ));
+    let choices = ["Reload from disk", "Keep buffer", "Show diff"];
+    let items = choices
+        .iter()
+        .enumerate()
+        .map(|(i, c)| Item::new(i, c.to_string()))
+        .collect();
+    app.item_picker.activate(items);
+    app.item_action = Some(ExternalChange);
+}
+
+/// Lists buffers dumped by a crashed run to pick one to restore, diff or discard.
+fn show_recovered(app: &mut App) {
+    let dumps = recovery::list();
+    if dumps.is_empty() {
+        return app.display_message(String::from("No recovered files"));
+    }
+    app.display_message(format!(
+        "{} unsaved files were recovered after a crash",
+        dumps.len()
+    ));
+    let items = dumps
+        .iter()
+        .enumerate()
+        .map(|(i, d)| Item::new(i, d.describe()))
+        .collect();
+    app.item_picker.activate(items);
+    app.item_action = Some(RecoveredFiles(dumps));
+}
+
+fn recovery_action(choice: usize, dump: recovery::Dump, buffer: &mut Buffer, app: &mut App) {
+    if choice < 2 && buffer.modified {
+        return app.display_message(String::from(
+            "No write since last change, save before restoring",
+        ));
+    }
+    let result = match choice {
+        0 => {
+            // keep the lines away from whatever file the buffer had if this one can't be opened
+            if !open_file(buffer, &dump.file, app) {
+                buffer.update(String::new(), &String::new());
+                app.watcher = None;
+            }
+            buffer.lines = dump.lines.clone();
+            buffer.modified = true;
+            dump.discard()
+        }
+        1 => dump.diff().map(|diff| {
+            let name = format!("{}.diff", dump.path.display());
+            buffer.update(diff.join("\n"), &name);
+            buffer.read_only = true;
+            app.display_message(String::from("Use :recover to restore or discard it"));
+        }),
+        _ => dump.discard(),
+    };
+    if let Err(msg) = result {
+        app.display_message(msg);
+    }
+}
+
+/// Writes the unsaved lines to the swap file when they changed since the last write.
+fn write_swap(buffer: &Buffer, app: &mut App) {
+    app.swap_written = Instant::now();
+    if buffer.file_name.is_empty() {
+        return;
+    }
+    let mut hasher = DefaultHasher::new();
+    buffer.lines.hash(&mut hasher);
+    let hash = hasher.finish();
+    if hash == app.swap_hash {
+        return;
+    }
+    app.swap_hash = hash;
+    if let Err(msg) = swap::write(&buffer.file_name, &buffer.lines) {
+        app.display_message(msg);
+    }
+}
+
+/// Saves the buffer to its file, read-only buffers only when `force` is set.
+fn save_buffer(buffer: &mut Buffer, app: &mut App, force: bool) {
+    if buffer.file_name.is_empty() {
+        return app.display_message(String::from("No file name (use :w <file>)"));
+    }
+    if buffer.read_only && !force {
+        return app.display_message(String::from("File is read-only (add ! to override)"));
+    }
+    if !force && app.watcher.as_ref().is_some_and(|w| w.is_stale()) {
+        return app.display_message(String::from(
+            "File changed on disk since reading it (add ! to override)",
+        ));
+    }
+    match file_assist::save_file(&buffer.file_name, &buffer.lines, &buffer.format) {
+        Ok(()) => {
+            buffer.saved();
+            buffer.read_only = false;
+            swap::remove(&buffer.file_name);
+            if let Some(watcher) = app.watcher.as_mut() {
+                watcher.reset();
+            }
+            app.display_message(format!("Saved {}", buffer.file_name));
+            if app.options.undofile {
+                if let Err(msg) = undo::save(&buffer.file_name, &buffer.lines, &buffer.history) {
+                    app.display_message(msg);
+                }
+            }
+        }
+        Err(msg) => app.display_message(msg),
+    }
+}
+
+fn handle_normal_action(
+    command: Command,
+    buffer: &mut Buffer,
+    input_mode: &mut InputMode,
+    visual_start: &mut Cursor,
+    app: &mut App,
+) {
+    let count = command.count;
+    // repeating past the end of the line or the buffer changes nothing, only takes long
+    let in_line = usize::min(count, buffer.lines[buffer.cursor.row as usize].len());
+    let in_buffer = usize::min(count, buffer.lines.len());
+    match command.action {
+        Action::Left => (0..in_line).for_each(|_| buffer.move_cursor(Direction::Left)),
+        Action::Right => (0..in_line).for_each(|_| buffer.move_cursor(Direction::Right)),
+        Action::Down => (0..in_buffer).for_each(|_| buffer.move_cursor(Direction::Down)),
+        Action::Up => (0..in_buffer).for_each(|_| buffer.move_cursor(Direction::Up)),
+
+        Action::DeleteLine => (0..in_buffer).for_each(|_| buffer.delete_line()),
+        Action::OpenLineBelow => {
+            buffer.insert_newline_below();
+            buffer.move_cursor(Direction::Down);
+            *input_mode = InputMode::Insert;
+        }
+        Action::Save => save_buffer(buffer, app, false),
+
+        Action::Insert => *input_mode = InputMode::Insert,
+        Action::Append => {
+            *input_mode = InputMode::Insert;
+            buffer.move_cursor(Direction::Right);
+        }
+        Action::DeleteChar => (0..in_line).for_each(|_| buffer.delete_current_character()),
+        Action::Visual => {
+            *visual_start = buffer.cursor;
+            *input_mode = InputMode::Visual;
+        }
+
+        Action::SurroundAdd(m, target) => {
+            if let Some(range) = motion::range(&buffer.lines, buffer.cursor_pos(), m, count) {
+                surround::add(buffer, range, &target);
+            }
+        }
+        Action::SurroundChange(old, new) => {
+            surround::change(buffer, old, &new);
+        }
+        Action::SurroundDelete(old) => {
+            surround::delete(buffer, old);
+        }
+
+        Action::Replace => *input_mode = InputMode::Replace,
+        Action::ReplaceChar(c) => {
+            buffer.replace_chars(c, count);
+        }
+        Action::ToggleCase => buffer.toggle_case(count),
+        Action::Increment(sign) => {
+            let delta = sign.saturating_mul(i64::try_from(count).unwrap_or(i64::MAX));
+            if let Some(end) = buffer.increment_number(buffer.cursor_pos(), delta) {
+                buffer.set_cursor_pos(end);
+            }
+        }
+        Action::ChangeCase(case, m) => {
+            let pos = buffer.cursor_pos();
+            let range = match m {
+                Motion::Line => motion::range(&buffer.lines, Pos::new(pos.row, 0), m, count)
+                    .map(|r| Range::new(Pos::new(r.start.row, 0), r.end)),
+                _ => motion::range(&buffer.lines, pos, m, count),
+            };
+            if let Some(range) = range {
+                buffer.change_case(range, case);
+                buffer.set_cursor_pos(range.start);
+            }
+        }
+        Action::Join(spaces) => buffer.join_lines(count, spaces),
+        Action::Format(m) => {
+            if let Some(range) = motion::range(&buffer.lines, buffer.cursor_pos(), m, count) {
+                format_lines(buffer, range.start.row, range.end.row, &app.options);
+            }
+        }
+        Action::CommandLine => {
+            app.command_line.clear();
+            *input_mode = InputMode::Command;
+        }
+        Action::Undo => {
+            if (0..count).take_while(|_| buffer.undo()).count() == 0 {
+                app.display_message(String::from("Already at oldest change"));
+            }
+        }
+        Action::Redo => {
+            if (0..count).take_while(|_| buffer.redo()).count() == 0 {
+                app.display_message(String::from("Already at newest change"));
+            }
+        }
+        Action::Filter(m) => {
+            if let Some(range) = motion::range(&buffer.lines, buffer.cursor_pos(), m, count) {
+                app.command_line = format!("{},{}!", range.start.row + 1, range.end.row + 1);
+                *input_mode = InputMode::Command;
+            }
+        }
+    }
+}
+
+fn handle_visual_action(
+    action: VisualAction,
+    buffer: &mut Buffer,
+    input_mode: &mut InputMode,
+    visual_start: Cursor,
+    app: &mut App,
+) {
+    let range = visual_range(buffer, visual_start);
+    match action {
+        VisualAction::Left => return buffer.move_cursor(Direction::Left),
+        VisualAction::Right => return buffer.move_cursor(Direction::Right),
+        VisualAction::Down => return buffer.move_cursor(Direction::Down),
+        VisualAction::Up => return buffer.move_cursor(Direction::Up),
+        VisualAction::Surround(target) => {
+            surround::add(buffer, range, &target);
+        }
+        VisualAction::ChangeCase(case) => {
+            buffer.change_case(range, case);
+            buffer.set_cursor_pos(range.start);
+        }
+        VisualAction::Increment(delta, progressive) => {
+            let mut step = 1;
+            for row in range.start.row..=range.end.row {
+                let col = if row == range.start.row {
+                    range.start.col
+                } else {
+                    0
+                };
+                let delta = if progressive {
+                    delta.saturating_mul(step)
+                } else {
+                    delta
+                };
+                if buffer.increment_number(Pos::new(row, col), delta).is_some() {
+                    step += 1;
+                }
+            }
+            buffer.set_cursor_pos(range.start);
+        }
+        VisualAction::Join(spaces) => {
+            buffer.set_cursor_pos(Pos::new(range.start.row, 0));
+            buffer.join_lines(range.end.row - range.start.row + 1, spaces);
+        }
+        VisualAction::Format => {
+            format_lines(buffer, range.start.row, range.end.row, &app.options);
+        }
+        VisualAction::CommandLine | VisualAction::Filter => {
+            app.visual_marks = Some((range.start.row, range.end.row));
+            app.command_line = String::from("'<,'>");
+            if action == VisualAction::Filter {
+                app.command_line.push('!');
+            }
+            *input_mode = InputMode::Command;
+            return;
+        }
+    }
+    *input_mode = InputMode::Normal;
+}
+
+fn format_lines(buffer: &mut Buffer, first: usize, last: usize, options: &Options) {
+    let formatted = format::reflow(
+        &buffer.lines[first..=last],
+        options.textwidth,
+        &options.comments,
+    );
+    let last_row = first + formatted.len().saturating_sub(1);
+    buffer.replace_lines(first, last, formatted);
+    let line = &buffer.lines[last_row];
+    buffer.set_cursor_pos(Pos::new(last_row, motion::first_non_blank(line)));
+}
+
+fn execute_command(line: &str, buffer: &mut Buffer, app: &mut App) {
+    let ctx = ex::Context {
+        cursor_row: buffer.cursor.row as usize,
+        last_row: buffer.lines.len() - 1,
+        visual: app.visual_marks,
+    };
+    let command = match ex::parse(line, &ctx) {
+        Ok(command) => command,
+        Err(msg) => return app.display_message(msg),
+    };
+    let (first, last) = command.range.unwrap_or((0, ctx.last_row));
+    match command.name.as_str() {
+        "" => {
+            if command.range.is_some() {
+                buffer.set_cursor_pos(Pos::new(last, 0));
+            }
+        }
+        "sort" | "sor" => match format::SortOptions::parse(command.bang, &command.args) {
+            Ok(options) => {
+                let sorted = format::sort(&buffer.lines[first..=last], &options);
+                buffer.replace_lines(first, last, sorted);
+            }
+            Err(msg) => app.display_message(msg),
+        },
+        "write" | "w" if command.args.is_empty() => save_buffer(buffer, app, command.bang),
+        "write" | "w" => {
+            let lines = &buffer.lines[first..=last];
+            match file_assist::save_file(&command.args, lines, &buffer.format) {
+                Ok(()) => app.display_message(format!("Written {}", command.args)),
+                Err(msg) => app.display_message(msg),
+            }
+        }
+        "recover" => show_recovered(app),
+        "mksession" | "mks" => {
+            let path = match command.args.as_str() {
+                "" => PathBuf::from(session::SESSION_FILE),
+                args => PathBuf::from(args),
+            };
+            if path.exists() && !command.bang {
+                return app
+                    .display_message(format!("{} exists (add ! to override)", path.display()));
+            }
+            app.session_request = Some(path);
+        }
+        "edit" | "e" => {
+            if buffer.modified && !command.bang {
+                return app.display_message(String::from(
+                    "No write since last change (add ! to override)",
+                ));
+            }
+            let filename = if command.args.is_empty() {
+                buffer.file_name.clone()
+            } else {
+                command.args.clone()
+            };
+            let previous = buffer.file_name.clone();
+            let opened = open_file(buffer, &filename, app);
+            discard_swap(&previous, opened, command.bang);
+        }
+        "next" | "n" | "previous" | "prev" | "N" => {
+            let index = match command.name.as_str() {
+                "next" | "n" => app.arg_index + 1,
+                _ => app.arg_index.wrapping_sub(1),
+            };
+            if index >= app.arg_list.len() {
+                return app.display_message(String::from("No more files in the argument list"));
+            }
+            if buffer.modified && !command.bang {
+                return app.display_message(String::from(
+                    "No write since last change (add ! to override)",
+                ));
+            }
+            let previous = buffer.file_name.clone();
+            let opened = open_arg(buffer, index, app);
+            discard_swap(&previous, opened, command.bang);
+        }
+        "args" | "ar" => {
+            let names: Vec<String> = app
+                .arg_list
+                .iter()
+                .enumerate()
+                .map(|(i, arg)| match i == app.arg_index {
+                    true => format!("[{}]", arg.path),
+                    false => arg.path.clone(),
+                })
+                .collect();
+            app.display_message(names.join(" "));
+        }
+        "files" => app.file_picker_request = Some(FilePickerAction::OpenFile),
+        "schemefiles" => app.file_picker_request = Some(FilePickerAction::ChangeColorScheme),
+        "reloadscheme" => app.reload_color_scheme(),
+        "colorscheme" | "colo" if !command.args.is_empty() => {
+            app.set_color_scheme(command.args.clone())
+        }
+        "colorscheme" | "colo" => {
+            let names = assets::list_themes();
+            let schemes: Vec<_> = names
+                .iter()
+                .map(|name| ColorScheme::read_from_file(name).map(|(cs, _)| cs))
+                .collect();
+            let items = names
+                .iter()
+                .zip(&schemes)
+                .enumerate()
+                .map(|(i, (name, cs))| match cs {
+                    Ok(cs) => Item::new(i, name.clone()).with_swatch(cs.swatch()),
+                    Err(_) => Item::new(i, name.clone()),
+                })
+                .collect();
+            app.item_picker.activate(items);
+            app.item_action = Some(ChangeColorScheme(SchemePreview {
+                names,
+                schemes,
+                previous: app.cs.clone(),
+            }));
+        }
+        "theme-import" => {
+            let mut args = command.args.split_whitespace();
+            match (args.next(), args.next()) {
+                (Some(file), name) => match theme_import::import_file(file, name) {
+                    Ok((path, unmapped)) => {
+                        app.set_color_scheme(path.to_string_lossy().to_string());
+                        let mut msg = format!("Imported {}", path.display());
+                        if !unmapped.is_empty() {
+                            let shown = unmapped.iter().take(5).cloned().collect::<Vec<_>>();
+                            msg +=
+                                &format!(", {} not mapped: {}", unmapped.len(), shown.join(", "));
+                        }
+                        app.display_message(msg);
+                    }
+                    Err(msg) => app.display_message(msg),
+                },
+                (None, _) => {
+                    app.display_message(String::from("Usage: :theme-import {file} [name]"))
+                }
+            }
+        }
+        "highlight" | "hi" if command.args.is_empty() => {
+            app.display_message(String::from("Usage: :highlight {group}"))
+        }
+        "highlight" | "hi" => {
+            let group = command.args.as_str();
+            let link = match app.cs.highlights.get(group) {
+                Some(Group::Link(target)) => format!(" -> {}", target),
+                _ => String::new(),
+            };
+            let style = app.cs.style(group).describe();
+            app.display_message(format!("{}{}: {}", group, link, style));
+        }
+        "commands" => {
+            app.item_picker.activate(vec![]);
+            app.item_action = Some(ExecuteCommand);
+        }
+        name if keymap::Mode::of_command(name).is_some() => {
+            let (modes, unmap) = keymap::Mode::of_command(name).unwrap();
+            map_command(&modes, unmap, &command.args, app);
+        }
+        "source" | "so" => {
+            if !command.args.is_empty() {
+                app.config_path = Some(PathBuf::from(&command.args));
+            }
+            if app.config_path.is_none() {
+                return app.display_message(String::from("No config file to source"));
+            }
+            app.source_config();
+            app.options = app.config.options_for(&buffer.file_name);
+            if let Some(scheme) = app.config.color_scheme.clone() {
+                app.set_color_scheme(scheme);
+            }
+            app.font_request = true;
+        }
+        "set" | "se" => {
+            let result = match command.args.split_once('=') {
+                Some(("fileformat", value)) | Some(("ff", value)) => {
+                    LineEnding::from_name(value.trim()).map(|e| buffer.set_line_ending(e))
+                }
+                Some(("fileencoding", value)) | Some(("fenc", value)) => {
+                    Encoding::from_name(value.trim()).map(|e| buffer.set_encoding(e))
+                }
+                _ => app.options.set(&command.args),
+            };
+            if let Err(msg) = result {
+                app.display_message(msg);
+            }
+        }
+        "!" => {
+            if app.filter_job.is_some() {
+                return app.display_message(String::from("A filter command is already running"));
+            }
+            let input = match command.range {
+                Some(_) => buffer.lines[first..=last].to_vec(),
+                None => Vec::new(),
+            };
+            match filter::spawn(&command.args, command.range, input) {
+                Ok(job) => app.filter_job = Some(job),
+                Err(msg) => app.display_message(msg),
+            }
+        }
+        _ => app.display_message(format!("Not an editor command: {}", line)),
+    }
+}
+
+/// Applies a finished filter, unless the lines it read were changed in the meantime.
+fn finish_filter(
+    job: FilterJob,
+    result: Result<Vec<String>, String>,
+    buffer: &mut Buffer,
+    app: &mut App,
+) {
+    match (result, job.rows) {
+        (Err(stderr), _) => app.display_message(stderr),
+        (Ok(output), None) => app.display_message(output.join(" | ")),
+        (Ok(output), Some((first, last))) => {
+            if buffer.lines.get(first..=last) != Some(&job.input[..]) {
+                return app.display_message(format!(
+                    "Lines changed while running {}, filter discarded",
+                    job.command
+                ));
+            }
+            let count = output.len();
+            buffer.begin_change();
+            buffer.replace_lines(first, last, output);
+            buffer.end_change();
+            let row = usize::min(first, buffer.lines.len() - 1);
+            buffer.set_cursor_pos(Pos::new(row, 0));
+            app.display_message(format!("{} lines filtered", count));
+        }
+    }
+}
+
+/// Characterwise selection between the visual start and the cursor, both inclusive.
+fn visual_range(buffer: &Buffer, visual_start: Cursor) -> Range {
+    let anchor = Pos::new(visual_start.row as usize, visual_start.col as usize);
+    let cursor = buffer.cursor_pos();
+    let (start, end) = if anchor <= cursor {
+        (anchor, cursor)
+    } else {
+        (cursor, anchor)
+    };
+    let line = &buffer.lines[end.row];
+    let end_col = usize::min(end.col, line.len());
+    let end_col = line[end_col..]
+        .chars()
+        .next()
+        .map_or(line.len(), |c| end_col + c.len_utf8());
+    Range::new(start, Pos::new(end.row, end_col))
+}
+
+/// Text committed by an input method, inserted as one undo step of its own.
+fn insert_composed(
+    text: &str,
+    input_mode: InputMode,
+    buffer: &mut Buffer,
+    replaced: &mut Vec<Option<char>>,
+) {
+    buffer.end_change();
+    buffer.begin_change();
+    for c in text.chars() {
+        match input_mode {
+            InputMode::Replace => replaced.push(buffer.replace_char(c)),
+            _ => buffer.insert_char(c),
+        }
+    }
+    buffer.end_change();
+    buffer.begin_change();
+}
+
+/// Key pressed for a command. Characters are typed through `Event::TextInput`
+/// instead, so they follow the keyboard layout.
+fn sdl_key(keycode: Keycode, ctrl: bool) -> Option<Key> {
     match keycode {
-        // numbers
-        Keycode::Num1 => buffer.insert_char(shft('1', '!', shift)),
-        Keycode::Num2 => buffer.insert_char(shft('2', '@', shift)),
-        Keycode::Num3 => buffer.insert_char(shft('3', '#', shift)),
-        Keycode::Num4 => buffer.insert_char(shft('4', '$', shift)),
-        Keycode::Num5 => buffer.insert_char(shft('5', '%', shift)),
-        Keycode::Num6 => buffer.insert_char(shft('6', '^', shift)),
-        Keycode::Num7 => buffer.insert_char(shft('7', '&', shift)),
-        Keycode::Num8 => buffer.insert_char(shft('8', '*', shift)),
-        Keycode::Num9 => buffer.insert_char(shft('9', '(', shift)),
-        Keycode::Num0 => buffer.insert_char(shft('0', ')', shift)),
-        Keycode::Minus => buffer.insert_char(shft('-', '_', shift)),
-        Keycode::Equals => buffer.insert_char(shft('=', '+', shift)),
-
-        // top row
-        Keycode::Q => buffer.insert_char(shft('q', 'Q', shift)),
-        Keycode::W => buffer.insert_char(shft('w', 'W', shift)),
-        Keycode::E => buffer.insert_char(shft('e', 'E', shift)),
-        Keycode::R => buffer.insert_char(shft('r', 'R', shift)),
-        Keycode::T => buffer.insert_char(shft('t', 'T', shift)),
-        Keycode::Y => buffer.insert_char(shft('y', 'Y', shift)),
-        Keycode::U => buffer.insert_char(shft('u', 'U', shift)),
-        Keycode::I => buffer.insert_char(shft('i', 'I', shift)),
-        Keycode::O => buffer.insert_char(shft('o', 'O', shift)),
-        Keycode::P => buffer.insert_char(shft('p', 'P', shift)),
-        Keycode::LeftBracket => buffer.insert_char(shft('[', '{', shift)),
-        Keycode::RightBracket => buffer.insert_char(shft(']', '}', shift)),
-        Keycode::Backslash => buffer.insert_char(shft('\\', '|', shift)),
-
-        // middle row
-        Keycode::A => buffer.insert_char(shft('a', 'A', shift)),
-        Keycode::S => buffer.insert_char(shft('s', 'S', shift)),
-        Keycode::D => buffer.insert_char(shft('d', 'D', shift)),
-        Keycode::F => buffer.insert_char(shft('f', 'F', shift)),
-        Keycode::G => buffer.insert_char(shft('g', 'G', shift)),
-        Keycode::H => buffer.insert_char(shft('h', 'H', shift)),
-        Keycode::J => buffer.insert_char(shft('j', 'J', shift)),
-        Keycode::K => buffer.insert_char(shft('k', 'K', shift)),
-        Keycode::L => buffer.insert_char(shft('l', 'L', shift)),
-        Keycode::Semicolon => buffer.insert_char(shft(';', ':', shift)),
-        Keycode::Quote => buffer.insert_char(shft('\'', '"', shift)),
-
-        // bottom row
-        Keycode::Z => buffer.insert_char(shft('z', 'Z', shift)),
-        Keycode::X => buffer.insert_char(shft('x', 'X', shift)),
-        Keycode::C => buffer.insert_char(shft('c', 'C', shift)),
-        Keycode::V => buffer.insert_char(shft('v', 'V', shift)),
-        Keycode::B => buffer.insert_char(shft('b', 'B', shift)),
-        Keycode::N => buffer.insert_char(shft('n', 'N', shift)),
-        Keycode::M => buffer.insert_char(shft('m', 'M', shift)),
-        Keycode::Comma => buffer.insert_char(shft(',', '<', shift)),
-        Keycode::Period => buffer.insert_char(shft('.', '>', shift)),
-        Keycode::Slash => buffer.insert_char(shft('/', '?', shift)),
-
-        // other
-        Keycode::Space => buffer.insert_char(' '),
-
-        Keycode::Return => {
+        Keycode::Return | Keycode::KpEnter => Some(Key::Enter),
+        Keycode::Escape => Some(Key::Escape),
+        Keycode::Backspace => Some(Key::Backspace),
+        Keycode::Tab => Some(Key::Tab),
+        Keycode::Up => Some(Key::Up),
+        Keycode::Down => Some(Key::Down),
+        Keycode::Left => Some(Key::Left),
+        Keycode::Right => Some(Key::Right),
+        // letter keycodes are the letters of the active layout
+        _ if ctrl => {
+            let c = std::char::from_u32(keycode as i32 as u32)?;
+            Some(Key::Ctrl(c)).filter(|_| c.is_ascii_lowercase())
+        }
+        _ => None,
+    }
+}
+
+/// Keymap table for the mode, or the picker's while one is open.
+fn keymap_mode(input_mode: InputMode, file_explorer: &FilePicker, app: &mut App) -> keymap::Mode {
+    if file_explorer.is_active() || app.item_picker.is_active() {
+        return keymap::Mode::Picker;
+    }
+    match input_mode {
+        InputMode::Normal => keymap::Mode::Normal,
+        InputMode::Insert | InputMode::Replace => keymap::Mode::Insert,
+        InputMode::Visual => keymap::Mode::Visual,
+        InputMode::Command => keymap::Mode::Command,
+    }
+}
+
+fn handle_key_ins_mode(key: Key, buffer: &mut Buffer) {
+    match key {
+        Key::Enter => {
             buffer.enter_newline();
             buffer.move_cursor(Direction::Down);
             buffer.move_cursor_beginning_line();
         }
 
-        Keycode::Backspace => {
-            if buffer.cursor.col > 0 {
-                buffer.move_cursor(Direction::Left);
-                buffer.delete_current_character();
-            }
+        Key::Backspace if buffer.cursor.col > 0 => {
+            buffer.move_cursor(Direction::Left);
+            buffer.delete_current_character();
+        }
+        Key::Char(c) => buffer.insert_char(c),
+        _ => (),
+    }
+}
+
+/// Replace mode remembers each overwritten character so Backspace can restore it.
+fn handle_key_replace_mode(key: Key, buffer: &mut Buffer, replaced: &mut Vec<Option<char>>) {
+    match key {
+        Key::Enter => {
+            buffer.enter_newline();
+            buffer.move_cursor(Direction::Down);
+            buffer.move_cursor_beginning_line();
+            replaced.clear();
         }
+        Key::Backspace => match replaced.pop() {
+            Some(old) => buffer.restore_char(old),
+            None => buffer.move_cursor(Direction::Left),
+        },
+        Key::Char(c) => replaced.push(buffer.replace_char(c)),
         _ => (),
     }
 }
@@ -0,0 +1,112 @@
+use crate::text_object::{self, Pos, Range, TextObject};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Motion {
+    WordForward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    /// Whole lines from the cursor down (`j`) or up (`k`).
+    LineDown,
+    LineUp,
+    /// Operator repeated (`yss`, `dd`), covers the current line.
+    Line,
+    Object(TextObject, bool),
+}
+
+impl Motion {
+    pub fn from_char(c: char) -> Option<Motion> {
+        match c {
+            'w' => Some(Motion::WordForward),
+            'e' => Some(Motion::WordEnd),
+            '0' => Some(Motion::LineStart),
+            '$' => Some(Motion::LineEnd),
+            'j' => Some(Motion::LineDown),
+            'k' => Some(Motion::LineUp),
+            _ => None,
+        }
+    }
+}
+
+/// Text covered by `motion` repeated `count` times, starting at `pos`.
+pub fn range(lines: &[String], pos: Pos, motion: Motion, count: usize) -> Option<Range> {
+    let line = lines.get(pos.row)?;
+    let at = |col: usize| Pos::new(pos.row, col);
+    // every step moves at least one byte, or stays at the end of the line
+    let steps = usize::min(count, line.len());
+    let repeat = |step: fn(&str, usize) -> usize| (0..steps).fold(pos.col, |c, _| step(line, c));
+    match motion {
+        Motion::WordForward => Some(Range::new(pos, at(repeat(word_forward)))),
+        Motion::WordEnd => Some(Range::new(pos, at(repeat(word_end)))),
+        Motion::LineStart => Some(Range::new(at(0), pos)),
+        Motion::LineEnd => Some(Range::new(pos, at(line.len()))),
+        Motion::Line => {
+            let last = usize::min(pos.row.saturating_add(count.max(1) - 1), lines.len() - 1);
+            Some(Range::new(
+                at(first_non_blank(line)),
+                Pos::new(last, lines[last].len()),
+            ))
+        }
+        Motion::LineDown => {
+            let last = usize::min(pos.row.saturating_add(count), lines.len() - 1);
+            Some(Range::new(at(0), Pos::new(last, lines[last].len())))
+        }
+        Motion::LineUp => Some(Range::new(
+            Pos::new(pos.row.saturating_sub(count), 0),
+            at(line.len()),
+        )),
+        Motion::Object(object, inner) => text_object::find(lines, pos, object, inner),
+    }
+}
+
+pub fn first_non_blank(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn same_class(a: char, b: char) -> bool {
+    a.is_whitespace() == b.is_whitespace() && is_word(a) == is_word(b)
+}
+
+/// Byte index where the next word starts, or the end of the line.
+fn word_forward(line: &str, col: usize) -> usize {
+    let mut chars = line[col..].char_indices().peekable();
+    if let Some(&(_, first)) = chars.peek() {
+        while let Some(&(_, c)) = chars.peek() {
+            if !same_class(first, c) {
+                break;
+            }
+            chars.next();
+        }
+    }
+    while let Some(&(_, c)) = chars.peek() {
+        if !c.is_whitespace() {
+            break;
+        }
+        chars.next();
+    }
+    chars.peek().map_or(line.len(), |&(i, _)| col + i)
+}
+
+/// Byte index just past the end of the current or next word.
+fn word_end(line: &str, col: usize) -> usize {
+    let mut chars = line[col..].char_indices().skip(1).peekable();
+    while let Some(&(_, c)) = chars.peek() {
+        if !c.is_whitespace() {
+            break;
+        }
+        chars.next();
+    }
+    if let Some(&(_, first)) = chars.peek() {
+        while let Some(&(_, c)) = chars.peek() {
+            if !same_class(first, c) {
+                break;
+            }
+            chars.next();
+        }
+    }
+    chars.peek().map_or(line.len(), |&(i, _)| col + i)
+}
@@ -0,0 +1,381 @@
+use crate::buffer::Case;
+use crate::motion::Motion;
+use crate::text_object::TextObject;
+use std::convert::TryFrom;
+
+/// Command typed in normal mode.
+#[derive(PartialEq, Debug)]
+pub enum Action {
+    Left,
+    Right,
+    Down,
+    Up,
+    DeleteLine,
+    DeleteChar,
+    OpenLineBelow,
+    Insert,
+    Append,
+    Save,
+    Visual,
+    SurroundAdd(Motion, String),
+    SurroundChange(char, String),
+    SurroundDelete(char),
+    Replace,
+    ReplaceChar(char),
+    ToggleCase,
+    ChangeCase(Case, Motion),
+    /// Ctrl-A (1) and Ctrl-X (-1), multiplied by the count.
+    Increment(i64),
+    /// `J` inserts spaces between the joined lines, `gJ` does not.
+    Join(bool),
+    Format(Motion),
+    CommandLine,
+    /// `!{motion}` prefills the command line with the range to filter.
+    Filter(Motion),
+    Undo,
+    Redo,
+}
+
+/// Normal mode action with the count typed before it (or inside it, as in `gU3w`).
+#[derive(PartialEq, Debug)]
+pub struct Command {
+    pub count: usize,
+    pub action: Action,
+}
+
+/// Command typed in visual mode.
+#[derive(PartialEq, Debug)]
+pub enum VisualAction {
+    Left,
+    Right,
+    Down,
+    Up,
+    Surround(String),
+    ChangeCase(Case),
+    /// Adds the delta to a number on every selected line, `g` makes it grow per line.
+    Increment(i64, bool),
+    Join(bool),
+    Format,
+    CommandLine,
+    Filter,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Parsed<T> {
+    Pending,
+    Invalid,
+    Done(T),
+}
+
+use Parsed::*;
+
+pub const CTRL_A: char = '\x01';
+pub const CTRL_R: char = '\x12';
+pub const CTRL_X: char = '\x18';
+
+impl<T> Parsed<T> {
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Parsed<U> {
+        match self {
+            Done(t) => Done(f(t)),
+            Pending => Pending,
+            Invalid => Invalid,
+        }
+    }
+
+    pub fn and_then<U, F: FnOnce(T) -> Parsed<U>>(self, f: F) -> Parsed<U> {
+        match self {
+            Done(t) => f(t),
+            Pending => Pending,
+            Invalid => Invalid,
+        }
+    }
+}
+
+/// Parses the keys typed so far in normal mode.
+pub fn parse(keys: &str) -> Parsed<Command> {
+    let chars: Vec<char> = keys.chars().collect();
+    let (count, used) = count(&chars);
+    action(&chars[used..]).map(|(action, inner_count)| Command {
+        count: count.saturating_mul(inner_count),
+        action,
+    })
+}
+
+/// Action with the count typed between an operator and its motion.
+fn action(chars: &[char]) -> Parsed<(Action, usize)> {
+    match chars {
+        ['g'] | ['g', 'u'] | ['g', 'U'] | ['g', '~'] | ['g', 'q'] => Pending,
+        ['g', 'J'] => Done((Action::Join(false), 1)),
+        ['!', rest @ ..] => {
+            let (count, used) = count(rest);
+            motion(&rest[used..], '!').map(|(m, _)| (Action::Filter(m), count))
+        }
+        ['g', 'q', rest @ ..] => {
+            let (count, used) = count(rest);
+            motion(&rest[used..], 'q').map(|(m, _)| (Action::Format(m), count))
+        }
+        ['g', op @ 'u', rest @ ..] | ['g', op @ 'U', rest @ ..] | ['g', op @ '~', rest @ ..] => {
+            let case = match op {
+                'u' => Case::Lower,
+                'U' => Case::Upper,
+                _ => Case::Toggle,
+            };
+            let (count, used) = count(rest);
+            motion(&rest[used..], *op).map(|(m, _)| (Action::ChangeCase(case, m), count))
+        }
+        ['y', 's', rest @ ..] => {
+            let (count, used) = count(rest);
+            let rest = &rest[used..];
+            motion(rest, 's').and_then(|(m, used)| {
+                target(&rest[used..]).map(|t| (Action::SurroundAdd(m, t), count))
+            })
+        }
+        _ => single_action(chars).map(|action| (action, 1)),
+    }
+}
+
+fn single_action(chars: &[char]) -> Parsed<Action> {
+    match chars {
+        [] => Pending,
+        ['h'] => Done(Action::Left),
+        ['l'] => Done(Action::Right),
+        ['j'] => Done(Action::Down),
+        ['k'] => Done(Action::Up),
+        ['x'] => Done(Action::DeleteChar),
+        ['o'] => Done(Action::OpenLineBelow),
+        ['i'] => Done(Action::Insert),
+        ['a'] => Done(Action::Append),
+        ['s'] | ['S'] => Done(Action::Save),
+        ['v'] => Done(Action::Visual),
+        ['R'] => Done(Action::Replace),
+        ['~'] => Done(Action::ToggleCase),
+        ['r'] => Pending,
+        ['r', c] => Done(Action::ReplaceChar(*c)),
+        [CTRL_A] => Done(Action::Increment(1)),
+        [CTRL_X] => Done(Action::Increment(-1)),
+        ['J'] => Done(Action::Join(true)),
+        [':'] => Done(Action::CommandLine),
+        ['u'] => Done(Action::Undo),
+        [CTRL_R] => Done(Action::Redo),
+        ['d'] | ['y'] | ['c'] => Pending,
+        ['d', 'd'] => Done(Action::DeleteLine),
+        ['d', 's'] | ['c', 's'] => Pending,
+        ['d', 's', old] => Done(Action::SurroundDelete(*old)),
+        ['c', 's', old, rest @ ..] => target(rest).map(|new| Action::SurroundChange(*old, new)),
+        _ => Invalid,
+    }
+}
+
+/// Parses the keys typed so far in visual mode.
+pub fn parse_visual(keys: &str) -> Parsed<VisualAction> {
+    let chars: Vec<char> = keys.chars().collect();
+    let (count, used) = count(&chars);
+    let delta = i64::try_from(count).unwrap_or(i64::MAX);
+    match &chars[used..] {
+        [] | ['g'] => Pending,
+        [CTRL_A] => Done(VisualAction::Increment(delta, false)),
+        [CTRL_X] => Done(VisualAction::Increment(-delta, false)),
+        ['g', CTRL_A] => Done(VisualAction::Increment(delta, true)),
+        ['g', CTRL_X] => Done(VisualAction::Increment(-delta, true)),
+        ['J'] => Done(VisualAction::Join(true)),
+        ['g', 'J'] => Done(VisualAction::Join(false)),
+        ['g', 'q'] => Done(VisualAction::Format),
+        [':'] => Done(VisualAction::CommandLine),
+        ['!'] => Done(VisualAction::Filter),
+        ['h'] => Done(VisualAction::Left),
+        ['l'] => Done(VisualAction::Right),
+        ['j'] => Done(VisualAction::Down),
+        ['k'] => Done(VisualAction::Up),
+        ['u'] => Done(VisualAction::ChangeCase(Case::Lower)),
+        ['U'] => Done(VisualAction::ChangeCase(Case::Upper)),
+        ['~'] => Done(VisualAction::ChangeCase(Case::Toggle)),
+        ['S', rest @ ..] => target(rest).map(VisualAction::Surround),
+        _ => Invalid,
+    }
+}
+
+/// Count prefix, `0` alone is a motion rather than a count. Counts too large
+/// for `usize` saturate.
+fn count(chars: &[char]) -> (usize, usize) {
+    if chars.first() == Some(&'0') {
+        return (1, 0);
+    }
+    let digits = chars.iter().take_while(|c| c.is_ascii_digit()).count();
+    let count = match digits {
+        0 => 1,
+        _ => chars[..digits]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .unwrap_or(usize::MAX),
+    };
+    (count, digits)
+}
+
+/// Motion following an operator, with the number of keys it used.
+fn motion(keys: &[char], operator: char) -> Parsed<(Motion, usize)> {
+    match keys {
+        [] => Pending,
+        [c, ..] if *c == operator => Done((Motion::Line, 1)),
+        ['i'] | ['a'] => Pending,
+        [kind @ 'i', c, ..] | [kind @ 'a', c, ..] => match TextObject::from_char(*c) {
+            Some(object) => Done((Motion::Object(object, *kind == 'i'), 2)),
+            None => Invalid,
+        },
+        [c, ..] => match Motion::from_char(*c) {
+            Some(m) => Done((m, 1)),
+            None => Invalid,
+        },
+    }
+}
+
+/// Surround target: a single character or a whole `<tag attr="x">`.
+fn target(keys: &[char]) -> Parsed<String> {
+    match keys {
+        [] => Pending,
+        ['<', rest @ ..] => match rest.iter().position(|&c| c == '>') {
+            Some(end) if end + 2 == keys.len() => Done(keys.iter().collect()),
+            Some(_) => Invalid,
+            None => Pending,
+        },
+        [c] => Done(c.to_string()),
+        _ => Invalid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surround_parse_test() {
+        assert_eq!(Pending, parse("ys"));
+        assert_eq!(Pending, parse("ysi"));
+        assert_eq!(Pending, parse("ysiw"));
+        assert_eq!(
+            Done(Action::SurroundAdd(
+                Motion::Object(TextObject::Word, true),
+                String::from(")")
+            )),
+            parse("ysiw)").map(|c| c.action)
+        );
+        assert_eq!(Pending, parse("yss<di"));
+        assert_eq!(
+            Done(Action::SurroundAdd(Motion::Line, String::from("<div>"))),
+            parse("yss<div>").map(|c| c.action)
+        );
+        assert_eq!(
+            Done(Action::SurroundChange('"', String::from("'"))),
+            parse("cs\"'").map(|c| c.action)
+        );
+        assert_eq!(
+            Done(Action::SurroundDelete('(')),
+            parse("ds(").map(|c| c.action)
+        );
+        assert_eq!(Invalid, parse("ysq"));
+        assert_eq!(
+            Done(VisualAction::Surround(String::from("\""))),
+            parse_visual("S\"")
+        );
+    }
+
+    #[test]
+    fn count_and_case_parse_test() {
+        assert_eq!(Pending, parse("1"));
+        assert_eq!(Pending, parse("12r"));
+        assert_eq!(
+            Done(Command {
+                count: 12,
+                action: Action::ReplaceChar('x')
+            }),
+            parse("12rx")
+        );
+        assert_eq!(Pending, parse("2gU3"));
+        assert_eq!(
+            Done(Command {
+                count: 6,
+                action: Action::ChangeCase(Case::Upper, Motion::WordForward)
+            }),
+            parse("2gU3w")
+        );
+        assert_eq!(
+            Done(Command {
+                count: 1,
+                action: Action::ChangeCase(Case::Toggle, Motion::Line)
+            }),
+            parse("g~~")
+        );
+        assert_eq!(Invalid, parse("0"));
+        assert_eq!(
+            Done(Command {
+                count: 5,
+                action: Action::Increment(-1)
+            }),
+            parse("5\x18")
+        );
+        assert_eq!(
+            Done(VisualAction::Increment(3, true)),
+            parse_visual("3g\x01")
+        );
+    }
+
+    #[test]
+    fn huge_count_parse_test() {
+        let count = |keys| match parse(keys) {
+            Done(command) => command.count,
+            _ => 0,
+        };
+        assert_eq!(usize::MAX, count("18446744073709551616j"));
+        assert_eq!(usize::MAX, count("99999999999gU99999999999w"));
+        assert_eq!(
+            Done(VisualAction::Increment(-i64::MAX, false)),
+            parse_visual("99999999999999999999\x18")
+        );
+    }
+
+    #[test]
+    fn join_and_format_parse_test() {
+        assert_eq!(
+            Done(Command {
+                count: 3,
+                action: Action::Join(false)
+            }),
+            parse("3gJ")
+        );
+        assert_eq!(
+            Done(Action::Format(Motion::Object(TextObject::Paragraph, false))),
+            parse("gqap").map(|c| c.action)
+        );
+        assert_eq!(
+            Done(Command {
+                count: 2,
+                action: Action::Format(Motion::LineDown)
+            }),
+            parse("gq2j")
+        );
+        assert_eq!(
+            Done(Action::Format(Motion::Line)),
+            parse("gqq").map(|c| c.action)
+        );
+        assert_eq!(Done(VisualAction::Format), parse_visual("gq"));
+        assert_eq!(
+            Done(Command {
+                count: 1,
+                action: Action::Filter(Motion::Object(TextObject::Paragraph, true))
+            }),
+            parse("!ip")
+        );
+    }
+
+    #[test]
+    fn undo_parse_test() {
+        assert_eq!(
+            Done(Command {
+                count: 3,
+                action: Action::Undo
+            }),
+            parse("3u")
+        );
+        assert_eq!(Done(Action::Redo), parse("\x12").map(|c| c.action));
+    }
+}
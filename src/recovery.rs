@@ -0,0 +1,177 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::buffer::Buffer;
+use crate::file_assist;
+
+/// File name and lines of every modified buffer, for the panic hook to dump.
+static MODIFIED: Mutex<Vec<(String, Vec<String>)>> = Mutex::new(Vec::new());
+
+/// Unsaved buffer written by the panic hook of an earlier run.
+#[derive(PartialEq, Debug)]
+pub struct Dump {
+    pub path: PathBuf,
+    pub file: String,
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub lines: Vec<String>,
+}
+
+fn recovery_dir() -> Option<PathBuf> {
+    file_assist::state_dir().map(|dir| dir.join("recovery"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Remembers the buffers to dump if the editor panics.
+pub fn track(buffers: &[&Buffer]) {
+    if let Ok(mut modified) = MODIFIED.lock() {
+        modified.clear();
+        for buffer in buffers.iter().filter(|b| b.modified) {
+            modified.push((absolute(&buffer.file_name), buffer.lines.clone()));
+        }
+    }
+}
+
+/// `file` from the root, to restore it whatever the working directory.
+fn absolute(file: &str) -> String {
+    if file.is_empty() {
+        return String::new();
+    }
+    std::path::absolute(file).map_or_else(|_| file.to_string(), |path| path.display().to_string())
+}
+
+/// Dumps tracked buffers before the default hook reports the panic.
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // try_lock: the panic may have happened while the lock was held
+        if let (Ok(modified), Some(dir)) = (MODIFIED.try_lock(), recovery_dir()) {
+            for (file, lines) in modified.iter() {
+                match write_dump(&dir, file, lines, now()) {
+                    Ok(path) => {
+                        eprintln!("Unsaved changes of {} saved to {}", file, path.display())
+                    }
+                    Err(e) => eprintln!("Unable to save unsaved changes of {}: {}", file, e),
+                }
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+fn write_dump(dir: &Path, file: &str, lines: &[String], time: u64) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let flat = file.replace('/', "%");
+    let path = dir.join(format!("{}-{}-{}", time, process::id(), flat));
+    fs::write(&path, format!("file={}\n\n{}", file, lines.join("\n")))?;
+    Ok(path)
+}
+
+fn read_dump(path: &Path) -> Option<Dump> {
+    let text = fs::read_to_string(path).ok()?;
+    let (header, body) = text.split_once("\n\n")?;
+    let file = header.strip_prefix("file=")?.to_string();
+    let name = path.file_name()?.to_string_lossy().to_string();
+    let time = name.split('-').next()?.parse().ok()?;
+    Some(Dump {
+        path: path.to_path_buf(),
+        file,
+        time,
+        lines: body.split('\n').map(String::from).collect(),
+    })
+}
+
+fn list_in(dir: &Path) -> Vec<Dump> {
+    let mut dumps: Vec<Dump> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| read_dump(&e.path()))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    dumps.sort_by_key(|d| Reverse(d.time));
+    dumps
+}
+
+/// Dumps left by earlier runs, newest first.
+pub fn list() -> Vec<Dump> {
+    recovery_dir().map(|dir| list_in(&dir)).unwrap_or_default()
+}
+
+fn age(seconds: u64) -> String {
+    match seconds {
+        0..=119 => String::from("just now"),
+        120..=7199 => format!("{} minutes ago", seconds / 60),
+        7200..=172_799 => format!("{} hours ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
+
+impl Dump {
+    pub fn describe(&self) -> String {
+        format!("{} ({})", self.file, age(now().saturating_sub(self.time)))
+    }
+
+    /// `diff -u` of the file on disk against the dump.
+    pub fn diff(&self) -> Result<Vec<String>, String> {
+        file_assist::diff(&self.file, &self.lines)
+    }
+
+    pub fn discard(&self) -> Result<(), String> {
+        fs::remove_file(&self.path)
+            .map_err(|e| format!("Unable to discard {}: {}", self.path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_test() {
+        let dir = std::env::temp_dir().join(format!("rslush-recovery-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let lines = vec![String::from("a"), String::new()];
+        write_dump(&dir, "/tmp/old.txt", &lines, 100).unwrap();
+        let path = write_dump(&dir, "new.txt", &lines, 200).unwrap();
+        let dumps = list_in(&dir);
+        assert_eq!(2, dumps.len());
+        assert_eq!(
+            Dump {
+                path,
+                file: String::from("new.txt"),
+                time: 200,
+                lines
+            },
+            dumps[0]
+        );
+        assert_eq!("/tmp/old.txt", dumps[1].file);
+        dumps[1].discard().unwrap();
+        assert_eq!(1, list_in(&dir).len());
+    }
+
+    #[test]
+    fn absolute_test() {
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(cwd.join("a.txt").display().to_string(), absolute("a.txt"));
+        assert_eq!("/tmp/a.txt", absolute("/tmp/a.txt"));
+        assert_eq!("", absolute(""));
+    }
+
+    #[test]
+    fn age_test() {
+        assert_eq!("just now", age(30));
+        assert_eq!("5 minutes ago", age(300));
+        assert_eq!("3 days ago", age(3 * 86400));
+    }
+}
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::file_assist;
+
+/// Written by `:mksession` and restored when starting in its directory.
+pub const SESSION_FILE: &str = ".rslush-session.json";
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct BufferSession {
+    pub file: String,
+    pub row: usize,
+    pub col: usize,
+    /// First line shown in the window.
+    pub scroll: usize,
+}
+
+/// Editor state worth restoring when the project is opened again.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct Session {
+    pub buffers: Vec<BufferSession>,
+    /// Index of the buffer shown in the window.
+    pub current: usize,
+    pub window_size: Option<(u32, u32)>,
+    pub window_position: Option<(i32, i32)>,
+    pub picker_directory: Option<String>,
+    pub color_scheme: Option<String>,
+}
+
+impl Session {
+    pub fn current_buffer(&self) -> Option<&BufferSession> {
+        self.buffers.get(self.current)
+    }
+
+    /// Records the argument list `args`, with `shown` as the current buffer. The
+    /// `index`th argument is replaced by it, or it is added when `:e` opened another file.
+    pub fn set_buffers(
+        &mut self,
+        mut args: Vec<BufferSession>,
+        index: usize,
+        shown: BufferSession,
+    ) {
+        self.current = index;
+        if shown.file.is_empty() {
+            // an unnamed buffer can't be reopened
+        } else if args.get(index).is_some_and(|arg| arg.file == shown.file) {
+            args[index] = shown;
+        } else {
+            self.current = args.len();
+            args.push(shown);
+        }
+        self.buffers = args;
+    }
+}
+
+/// Where the automatic session of the current directory is kept.
+pub fn auto_path() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    file_assist::state_file("sessions", &cwd.to_string_lossy(), "json")
+}
+
+/// `:mksession` file in the current directory, otherwise the automatic session if `auto`.
+pub fn find(auto: bool) -> Option<PathBuf> {
+    let explicit = PathBuf::from(SESSION_FILE);
+    if explicit.is_file() {
+        return Some(explicit);
+    }
+    auto_path().filter(|p| auto && p.is_file())
+}
+
+pub fn save(path: &Path, session: &Session) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(session).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, json).map_err(|e| format!("Unable to write session {}: {}", path.display(), e))
+}
+
+pub fn load(path: &Path) -> Result<Session, String> {
+    let json = fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read session {}: {}", path.display(), e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Invalid session {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_load_test() {
+        let dir = std::env::temp_dir().join(format!("rslush-session-{}", std::process::id()));
+        let path = dir.join("session.json");
+        let session = Session {
+            buffers: vec![BufferSession {
+                file: String::from("src/main.rs"),
+                row: 10,
+                col: 4,
+                scroll: 3,
+            }],
+            current: 0,
+            window_size: Some((800, 600)),
+            window_position: None,
+            picker_directory: Some(String::from("/tmp")),
+            color_scheme: Some(String::from("./colors/oceanic.json")),
+        };
+        assert_eq!(Ok(()), save(&path, &session));
+        assert_eq!(Ok(session), load(&path));
+    }
+
+    #[test]
+    fn set_buffers_test() {
+        let buffer = |file: &str, row| BufferSession {
+            file: String::from(file),
+            row,
+            ..BufferSession::default()
+        };
+        let mut session = Session::default();
+        session.set_buffers(vec![buffer("a", 0), buffer("b", 0)], 1, buffer("b", 5));
+        assert_eq!(
+            (vec![buffer("a", 0), buffer("b", 5)], 1),
+            (session.buffers.clone(), session.current)
+        );
+        session.set_buffers(vec![buffer("a", 0)], 0, buffer("c", 2));
+        assert_eq!(
+            (vec![buffer("a", 0), buffer("c", 2)], 1),
+            (session.buffers.clone(), session.current)
+        );
+        session.set_buffers(Vec::new(), 0, buffer("", 0));
+        assert_eq!(None, session.current_buffer());
+    }
+
+    #[test]
+    fn missing_fields_test() {
+        let session: Session = serde_json::from_str(r#"{"buffers": [{"file": "a"}]}"#).unwrap();
+        assert_eq!(Some("a"), session.current_buffer().map(|b| b.file.as_str()));
+        assert_eq!(None, session.color_scheme);
+    }
+}
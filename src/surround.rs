@@ -0,0 +1,144 @@
+use crate::buffer::Buffer;
+use crate::text_object::{self, Range, TextObject};
+
+/// Opening and closing text inserted for a surround target such as `(`, `"` or `<em>`.
+pub fn delimiters(target: &str) -> Option<(String, String)> {
+    let pair = |open: &str, close: &str| Some((open.to_string(), close.to_string()));
+    match target {
+        "(" => pair("( ", " )"),
+        ")" | "b" => pair("(", ")"),
+        "{" => pair("{ ", " }"),
+        "}" | "B" => pair("{", "}"),
+        "[" => pair("[ ", " ]"),
+        "]" | "r" => pair("[", "]"),
+        ">" | "a" => pair("<", ">"),
+        t if t.starts_with('<') && t.ends_with('>') && t.len() > 2 => {
+            let name: String = t[1..t.len() - 1]
+                .chars()
+                .take_while(|c| !c.is_whitespace())
+                .collect();
+            Some((t.to_string(), format!("</{}>", name)))
+        }
+        t if t.chars().count() == 1 => pair(t, t),
+        _ => None,
+    }
+}
+
+fn target_object(target: char) -> Option<TextObject> {
+    match TextObject::from_char(target) {
+        Some(TextObject::Word) | Some(TextObject::Paragraph) => None,
+
+        Some(object) => Some(object),
+        None if target.is_ascii_punctuation() => Some(TextObject::Quote(target)),
+        None => None,
+    }
+}
+
+/// Finds the delimited text around the cursor, returning the full range and the
+/// inner text. Opening brackets as targets also strip the padding whitespace.
+fn surrounding(buffer: &Buffer, target: char) -> Option<(Range, String)> {
+    let object = target_object(target)?;
+    let pos = buffer.cursor_pos();
+    let outer = text_object::find(&buffer.lines, pos, object, false)?;
+    let inner = text_object::find(&buffer.lines, pos, object, true)?;
+    let text = buffer.text_range(inner);
+    let text = if "({[".contains(target) {
+        text.trim_matches([' ', '\t']).to_string()
+    } else {
+        text
+    };
+    Some((outer, text))
+}
+
+pub fn add(buffer: &mut Buffer, range: Range, target: &str) -> bool {
+    match delimiters(target) {
+        Some((open, close)) => {
+            let text = buffer.text_range(range);
+            let trimmed = text.trim_end_matches([' ', '\t']);
+            let end = text_object::to_pos(
+                &buffer.lines,
+                text_object::to_offset(&buffer.lines, range.start) + trimmed.len(),
+            );
+            buffer.replace_range(
+                Range::new(range.start, end),
+                &format!("{}{}{}", open, trimmed, close),
+            );
+            buffer.set_cursor_pos(range.start);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn change(buffer: &mut Buffer, old: char, new: &str) -> bool {
+    match (surrounding(buffer, old), delimiters(new)) {
+        (Some((outer, text)), Some((open, close))) => {
+            buffer.replace_range(outer, &format!("{}{}{}", open, text, close));
+            buffer.set_cursor_pos(outer.start);
+            true
+        }
+        _ => false,
+    }
+}
+
+pub fn delete(buffer: &mut Buffer, old: char) -> bool {
+    match surrounding(buffer, old) {
+        Some((outer, text)) => {
+            buffer.replace_range(outer, &text);
+            buffer.set_cursor_pos(outer.start);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text_object::Pos;
+
+    fn buffer_at(text: &str, col: usize) -> Buffer {
+        let mut buffer = Buffer::new(text.to_string(), String::from("test"));
+        buffer.set_cursor_pos(Pos::new(0, col));
+        buffer
+    }
+
+    #[test]
+    fn add_test() {
+        let mut b = buffer_at("let x = value;", 8);
+        let word = Range::new(Pos::new(0, 8), Pos::new(0, 13));
+        assert!(add(&mut b, word, "("));
+        assert_eq!("let x = ( value );", b.lines[0]);
+
+        let mut b = buffer_at("hello world", 0);
+        let word = Range::new(Pos::new(0, 0), Pos::new(0, 6));
+        assert!(add(&mut b, word, "<em class=\"x\">"));
+        assert_eq!("<em class=\"x\">hello</em> world", b.lines[0]);
+    }
+
+    #[test]
+    fn change_test() {
+        let mut b = buffer_at("say \"hi\" now", 5);
+        assert!(change(&mut b, '"', "'"));
+        assert_eq!("say 'hi' now", b.lines[0]);
+
+        let mut b = buffer_at("f( a, b )", 4);
+        assert!(change(&mut b, '(', "]"));
+        assert_eq!("f[a, b]", b.lines[0]);
+
+        let mut b = buffer_at("<b>x</b>", 3);
+        assert!(change(&mut b, 't', "<i>"));
+        assert_eq!("<i>x</i>", b.lines[0]);
+    }
+
+    #[test]
+    fn delete_test() {
+        let mut b = buffer_at("call([1, 2])", 7);
+        assert!(delete(&mut b, ']'));
+        assert_eq!("call(1, 2)", b.lines[0]);
+
+        let mut b = buffer_at("plain", 2);
+        assert!(!delete(&mut b, '"'));
+        assert_eq!("plain", b.lines[0]);
+    }
+}
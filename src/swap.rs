@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::file_assist;
+
+const HEADER: &str = "rslush swap";
+
+/// Unsaved lines of a file, written periodically so they survive a crash.
+#[derive(PartialEq, Debug)]
+pub struct Swap {
+    pub path: PathBuf,
+    pub pid: u32,
+    pub lines: Vec<String>,
+}
+
+/// `.name.swp` next to the file, then a copy in the state directory for files in
+/// directories we can't write to.
+fn candidates(file: &str) -> Vec<PathBuf> {
+    let path = fs::canonicalize(file).unwrap_or_else(|_| PathBuf::from(file));
+    let mut paths = Vec::new();
+    if let (Some(dir), Some(name)) = (path.parent(), path.file_name()) {
+        paths.push(dir.join(format!(".{}.swp", name.to_string_lossy())));
+    }
+    paths.extend(file_assist::state_file("swap", file, "swp"));
+    paths
+}
+
+fn encode(pid: u32, file: &str, lines: &[String]) -> String {
+    format!(
+        "{}\npid={}\nfile={}\n\n{}",
+        HEADER,
+        pid,
+        file,
+        lines.join("\n")
+    )
+}
+
+fn decode(text: &str) -> Option<(u32, Vec<String>)> {
+    let (header, body) = text.split_once("\n\n")?;
+    let mut header = header.lines();
+    if header.next()? != HEADER {
+        return None;
+    }
+    let pid = header.next()?.strip_prefix("pid=")?.parse().ok()?;
+    Some((pid, body.split('\n').map(String::from).collect()))
+}
+
+/// First readable swap for `file`.
+pub fn find(file: &str) -> Option<Swap> {
+    candidates(file).into_iter().find_map(|path| {
+        let (pid, lines) = decode(&fs::read_to_string(&path).ok()?)?;
+        Some(Swap { path, pid, lines })
+    })
+}
+
+/// Writes the swap where possible, returning its path.
+pub fn write(file: &str, lines: &[String]) -> Result<PathBuf, String> {
+    let text = encode(std::process::id(), file, lines);
+    let mut error = format!("No place to write a swap file for {}", file);
+    for path in candidates(file) {
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        match fs::write(&path, &text) {
+            Ok(()) => return Ok(path),
+            Err(e) => error = format!("Unable to write swap file {}: {}", path.display(), e),
+        }
+    }
+    Err(error)
+}
+
+/// Removes swaps of `file` written by this process.
+pub fn remove(file: &str) {
+    while let Some(swap) = find(file).filter(|s| s.pid == std::process::id()) {
+        if fs::remove_file(&swap.path).is_err() {
+            break;
+        }
+    }
+}
+
+/// Whether `pid` is another rslush that may still be editing the file. Without
+/// `/proc` we can't tell, so assume it is.
+pub fn is_running(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return false;
+    }
+    if !Path::new("/proc/self").exists() {
+        return true;
+    }
+    fs::read_to_string(format!("/proc/{}/comm", pid)).is_ok_and(|comm| comm.trim() == "rslush")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_test() {
+        let lines = vec![String::from("a"), String::new(), String::from("b")];
+        assert_eq!(
+            Some((42, lines.clone())),
+            decode(&encode(42, "f.txt", &lines))
+        );
+        assert_eq!(None, decode("a\n\nb"));
+    }
+
+    #[test]
+    fn write_find_remove_test() {
+        let dir = std::env::temp_dir().join(format!("rslush-swap-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("notes.txt");
+        let file = file.to_str().unwrap();
+        let lines = vec![String::from("unsaved")];
+        assert_eq!(Ok(dir.join(".notes.txt.swp")), write(file, &lines));
+        let swap = find(file).unwrap();
+        assert_eq!((std::process::id(), lines), (swap.pid, swap.lines));
+        assert!(!is_running(swap.pid));
+        remove(file);
+        assert_eq!(None, find(file));
+    }
+}
@@ -0,0 +1,358 @@
+/// Position in a buffer, `col` is a byte index into the line.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Pos {
+    pub row: usize,
+    pub col: usize,
+}
+
+/// Half-open span of text, `end` is exclusive.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Range {
+    pub start: Pos,
+    pub end: Pos,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextObject {
+    Word,
+    Bracket(char, char),
+    Quote(char),
+    Tag,
+    Paragraph,
+}
+
+impl Pos {
+    pub fn new(row: usize, col: usize) -> Pos {
+        Pos { row, col }
+    }
+}
+
+impl Range {
+    pub fn new(start: Pos, end: Pos) -> Range {
+        Range { start, end }
+    }
+}
+
+impl TextObject {
+    /// Text object selected by the character following `i`/`a`, or a surround target.
+    pub fn from_char(c: char) -> Option<TextObject> {
+        match c {
+            'w' => Some(TextObject::Word),
+            '(' | ')' | 'b' => Some(TextObject::Bracket('(', ')')),
+            '{' | '}' | 'B' => Some(TextObject::Bracket('{', '}')),
+            '[' | ']' | 'r' => Some(TextObject::Bracket('[', ']')),
+            '<' | '>' | 'a' => Some(TextObject::Bracket('<', '>')),
+            't' => Some(TextObject::Tag),
+            'p' => Some(TextObject::Paragraph),
+            '"' | '\'' | '`' => Some(TextObject::Quote(c)),
+            _ => None,
+        }
+    }
+}
+
+pub fn to_offset(lines: &[String], pos: Pos) -> usize {
+    lines
+        .iter()
+        .take(pos.row)
+        .map(|l| l.len() + 1)
+        .sum::<usize>()
+        + pos.col
+}
+
+pub fn to_pos(lines: &[String], offset: usize) -> Pos {
+    let mut rest = offset;
+    for (row, l) in lines.iter().enumerate() {
+        if rest <= l.len() {
+            return Pos::new(row, rest);
+        }
+        rest -= l.len() + 1;
+    }
+    let last = lines.len().saturating_sub(1);
+    Pos::new(last, lines.get(last).map_or(0, |l| l.len()))
+}
+
+/// Finds the `inner` or `around` range of `object` containing `pos`.
+pub fn find(lines: &[String], pos: Pos, object: TextObject, inner: bool) -> Option<Range> {
+    let (outer_range, inner_range) = match object {
+        TextObject::Word => return word(lines, pos, inner),
+        TextObject::Paragraph => return paragraph(lines, pos, inner),
+        TextObject::Quote(q) => quote(lines, pos, q)?,
+        TextObject::Bracket(open, close) => {
+            let text = lines.join("\n");
+            let (s, e) = bracket(
+                text.as_bytes(),
+                to_offset(lines, pos),
+                open as u8,
+                close as u8,
+            )?;
+            ((s, e + 1), (s + 1, e))
+        }
+        TextObject::Tag => {
+            let text = lines.join("\n");
+            tag(&text, to_offset(lines, pos))?
+        }
+    };
+    let (s, e) = if inner { inner_range } else { outer_range };
+    Some(Range::new(to_pos(lines, s), to_pos(lines, e)))
+}
+
+fn bracket(text: &[u8], cur: usize, open: u8, close: u8) -> Option<(usize, usize)> {
+    let start = match text.get(cur) {
+        Some(&c) if c == open => cur,
+        Some(&c) if c == close => find_unmatched_back(text, cur, open, close)?,
+        _ => find_unmatched_back(text, cur, open, close)?,
+    };
+    let mut depth = 0;
+    for (i, &c) in text.iter().enumerate().skip(start + 1) {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                return Some((start, i));
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+fn find_unmatched_back(text: &[u8], before: usize, open: u8, close: u8) -> Option<usize> {
+    let mut depth = 0;
+    for i in (0..before.min(text.len())).rev() {
+        if text[i] == close {
+            depth += 1;
+        } else if text[i] == open {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+type Spans = ((usize, usize), (usize, usize));
+
+fn quote(lines: &[String], pos: Pos, q: char) -> Option<Spans> {
+    let line = lines.get(pos.row)?.as_bytes();
+    let mut quotes = Vec::new();
+    let mut escaped = false;
+    for (i, &c) in line.iter().enumerate() {
+        if escaped {
+            escaped = false;
+        } else if c == b'\\' {
+            escaped = true;
+        } else if c == q as u8 {
+            quotes.push(i);
+        }
+    }
+    let (s, e) = quotes
+        .chunks_exact(2)
+        .map(|p| (p[0], p[1]))
+        .find(|&(s, e)| s <= pos.col && pos.col <= e)
+        .or_else(|| {
+            quotes
+                .chunks_exact(2)
+                .map(|p| (p[0], p[1]))
+                .find(|&(s, _)| s > pos.col)
+        })?;
+    let base = to_offset(lines, Pos::new(pos.row, 0));
+    Some(((base + s, base + e + 1), (base + s + 1, base + e)))
+}
+
+struct TagToken {
+    start: usize,
+    end: usize,
+    name: String,
+    closing: bool,
+}
+
+fn tag_tokens(text: &str) -> Vec<TagToken> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'<' {
+            i += 1;
+            continue;
+        }
+        let closing = bytes.get(i + 1) == Some(&b'/');
+        let name_start = if closing { i + 2 } else { i + 1 };
+        let name_end = bytes[name_start.min(bytes.len())..]
+            .iter()
+            .position(|c| !(c.is_ascii_alphanumeric() || *c == b'-' || *c == b':' || *c == b'_'))
+            .map_or(bytes.len(), |p| name_start + p);
+        let close = bytes[i..].iter().position(|&c| c == b'>').map(|p| i + p);
+        match close {
+            Some(end) if name_end > name_start && bytes[end - 1] != b'/' => {
+                tokens.push(TagToken {
+                    start: i,
+                    end: end + 1,
+                    name: text[name_start..name_end].to_string(),
+                    closing,
+                });
+                i = end + 1;
+            }
+            _ => i += 1,
+        }
+    }
+    tokens
+}
+
+fn tag(text: &str, cur: usize) -> Option<Spans> {
+    let mut stack: Vec<TagToken> = Vec::new();
+    let mut best: Option<Spans> = None;
+    for token in tag_tokens(text) {
+        if !token.closing {
+            stack.push(token);
+            continue;
+        }
+        if let Some(idx) = stack.iter().rposition(|t| t.name == token.name) {
+            let open = stack.remove(idx);
+            stack.truncate(idx);
+            if open.start <= cur && cur < token.end {
+                let outer = (open.start, token.end);
+                if best.is_none_or(|(b, _)| outer.1 - outer.0 < b.1 - b.0) {
+                    best = Some((outer, (open.end, token.start)));
+                }
+            }
+        }
+    }
+    best
+}
+
+#[derive(PartialEq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punct,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punct
+    }
+}
+
+fn word(lines: &[String], pos: Pos, inner: bool) -> Option<Range> {
+    let line = lines.get(pos.row)?;
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let idx = chars.iter().position(|&(i, _)| i >= pos.col)?;
+    let cls = class(chars[idx].1);
+    let mut s = idx;
+    while s > 0 && class(chars[s - 1].1) == cls {
+        s -= 1;
+    }
+    let mut e = idx + 1;
+    while e < chars.len() && class(chars[e].1) == cls {
+        e += 1;
+    }
+    if !inner && cls != CharClass::Blank {
+        while e < chars.len() && class(chars[e].1) == CharClass::Blank {
+            e += 1;
+        }
+    }
+    let byte = |i: usize| chars.get(i).map_or(line.len(), |&(b, _)| b);
+    Some(Range::new(
+        Pos::new(pos.row, byte(s)),
+        Pos::new(pos.row, byte(e)),
+    ))
+}
+
+/// Consecutive lines that are all blank or all non-blank, `around` adds the blank
+/// lines that follow.
+fn paragraph(lines: &[String], pos: Pos, inner: bool) -> Option<Range> {
+    let blank = |row: usize| lines[row].trim().is_empty();
+    let kind = blank(pos.row);
+    let mut start = pos.row;
+    while start > 0 && blank(start - 1) == kind {
+        start -= 1;
+    }
+    let mut end = pos.row;
+    while end + 1 < lines.len() && blank(end + 1) == kind {
+        end += 1;
+    }
+    if !inner && !kind {
+        while end + 1 < lines.len() && blank(end + 1) {
+            end += 1;
+        }
+    }
+    Some(Range::new(
+        Pos::new(start, 0),
+        Pos::new(end, lines[end].len()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(String::from).collect()
+    }
+
+    fn range_text(ls: &[String], r: Range) -> String {
+        let text = ls.join("\n");
+        text[to_offset(ls, r.start)..to_offset(ls, r.end)].to_string()
+    }
+
+    #[test]
+    fn bracket_object_test() {
+        let ls = lines("f(a, (b), c)");
+        let obj = TextObject::Bracket('(', ')');
+        let r = find(&ls, Pos::new(0, 3), obj, true).unwrap();
+        assert_eq!("a, (b), c", range_text(&ls, r));
+        let r = find(&ls, Pos::new(0, 6), obj, false).unwrap();
+        assert_eq!("(b)", range_text(&ls, r));
+        let r = find(&ls, Pos::new(0, 11), obj, false).unwrap();
+        assert_eq!("(a, (b), c)", range_text(&ls, r));
+        assert_eq!(None, find(&ls, Pos::new(0, 0), obj, false));
+    }
+
+    #[test]
+    fn bracket_multiline_test() {
+        let ls = lines("if x {\n  y\n}");
+        let r = find(&ls, Pos::new(1, 2), TextObject::Bracket('{', '}'), false).unwrap();
+        assert_eq!(Range::new(Pos::new(0, 5), Pos::new(2, 1)), r);
+    }
+
+    #[test]
+    fn quote_object_test() {
+        let ls = lines(r#"say "hi \"there\"" and 'x'"#);
+        let r = find(&ls, Pos::new(0, 6), TextObject::Quote('"'), true).unwrap();
+        assert_eq!(r#"hi \"there\""#, range_text(&ls, r));
+        let r = find(&ls, Pos::new(0, 0), TextObject::Quote('\''), false).unwrap();
+        assert_eq!("'x'", range_text(&ls, r));
+    }
+
+    #[test]
+    fn tag_object_test() {
+        let ls = lines("<p>a <b>bold</b> c</p>");
+        let r = find(&ls, Pos::new(0, 9), TextObject::Tag, true).unwrap();
+        assert_eq!("bold", range_text(&ls, r));
+        let r = find(&ls, Pos::new(0, 3), TextObject::Tag, false).unwrap();
+        assert_eq!("<p>a <b>bold</b> c</p>", range_text(&ls, r));
+    }
+
+    #[test]
+    fn paragraph_object_test() {
+        let ls = lines("a\nb\n\n\nc");
+        let r = find(&ls, Pos::new(1, 0), TextObject::Paragraph, true).unwrap();
+        assert_eq!(Range::new(Pos::new(0, 0), Pos::new(1, 1)), r);
+        let r = find(&ls, Pos::new(0, 0), TextObject::Paragraph, false).unwrap();
+        assert_eq!(Range::new(Pos::new(0, 0), Pos::new(3, 0)), r);
+    }
+
+    #[test]
+    fn word_object_test() {
+        let ls = lines("let foo_bar = 1;");
+        let r = find(&ls, Pos::new(0, 6), TextObject::Word, true).unwrap();
+        assert_eq!("foo_bar", range_text(&ls, r));
+        let r = find(&ls, Pos::new(0, 6), TextObject::Word, false).unwrap();
+        assert_eq!("foo_bar ", range_text(&ls, r));
+    }
+}
@@ -0,0 +1,620 @@
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::color::{color_to_string, parse_color, Palette};
+use crate::file_assist;
+
+/// VS Code `colors` keys with the group they set and whether it is the background.
+const VSCODE_COLORS: &[(&str, &str, bool)] = &[
+    ("editor.foreground", "Normal", false),
+    ("editor.background", "Normal", true),
+    ("editorCursor.foreground", "Cursor", true),
+    ("editor.selectionBackground", "Visual", true),
+    ("editor.findMatchHighlightBackground", "Search", true),
+    ("editorLineNumber.foreground", "LineNr", false),
+    ("statusBar.foreground", "StatusLine", false),
+    ("statusBar.background", "StatusLine", true),
+    ("quickInput.foreground", "FilePicker", false),
+    ("quickInput.background", "FilePicker", true),
+    ("focusBorder", "FilePicker.Border", false),
+    (
+        "list.activeSelectionBackground",
+        "FilePicker.Selection",
+        true,
+    ),
+    ("editorError.foreground", "DiagnosticError", false),
+    ("editorWarning.foreground", "DiagnosticWarning", false),
+    ("editorInfo.foreground", "DiagnosticInfo", false),
+    ("editorHint.foreground", "DiagnosticHint", false),
+];
+
+/// Settings of a TextMate rule without a scope, which apply to the whole editor.
+const TEXTMATE_GLOBALS: &[(&str, &str, bool)] = &[
+    ("foreground", "Normal", false),
+    ("background", "Normal", true),
+    ("caret", "Cursor", true),
+    ("selection", "Visual", true),
+    ("findHighlight", "Search", true),
+    ("gutterForeground", "LineNr", false),
+    ("gutter", "LineNr", true),
+];
+
+/// TextMate scopes and their groups; a scope also covers the scopes below it.
+const SCOPES: &[(&str, &str)] = &[
+    ("comment", "Comment"),
+    ("string", "String"),
+    ("constant.numeric", "Number"),
+    ("constant", "Constant"),
+    ("keyword.operator", "Operator"),
+    ("keyword", "Keyword"),
+    ("storage.type", "Type"),
+    ("storage", "Keyword"),
+    ("entity.name.function", "Function"),
+    ("support.function", "Function"),
+    ("entity.name.type", "Type"),
+    ("entity.name.class", "Type"),
+    ("support.type", "Type"),
+    ("support.class", "Type"),
+    ("variable", "Identifier"),
+    ("invalid", "DiagnosticError"),
+];
+
+/// Groups of a base16 scheme, as `(group, fg, bg)` palette entries.
+const BASE16_GROUPS: &[(&str, Option<&str>, Option<&str>)] = &[
+    ("Normal", Some("base05"), Some("base00")),
+    ("Cursor", None, Some("base05")),
+    ("StatusLine", Some("base04"), Some("base01")),
+    ("Visual", None, Some("base02")),
+    ("Search", Some("base00"), Some("base0A")),
+    ("LineNr", Some("base03"), None),
+    ("FilePicker", Some("base05"), Some("base01")),
+    ("FilePicker.Border", Some("base0D"), None),
+    ("FilePicker.Selection", None, Some("base02")),
+    ("Comment", Some("base03"), None),
+    ("Constant", Some("base09"), None),
+    ("String", Some("base0B"), None),
+    ("Identifier", Some("base08"), None),
+    ("Function", Some("base0D"), None),
+    ("Keyword", Some("base0E"), None),
+    ("Type", Some("base0A"), None),
+    ("Operator", Some("base0C"), None),
+    ("DiagnosticError", Some("base08"), None),
+    ("DiagnosticWarning", Some("base0A"), None),
+    ("DiagnosticInfo", Some("base0D"), None),
+    ("DiagnosticHint", Some("base0C"), None),
+];
+
+/// Keys of a base16 file that describe it rather than set colors.
+const BASE16_INFO: &[&str] = &[
+    "scheme",
+    "author",
+    "name",
+    "slug",
+    "system",
+    "variant",
+    "description",
+    "palette",
+];
+
+/// A color scheme converted to rslush, with the keys of the original it left out.
+#[derive(PartialEq, Debug)]
+pub struct Imported {
+    pub scheme: Value,
+    pub unmapped: Vec<String>,
+}
+
+#[derive(Default)]
+struct Converter {
+    groups: BTreeMap<String, Map<String, Value>>,
+    /// Scope segments below the mapped scope, for rules that set a group.
+    depth: BTreeMap<String, usize>,
+    unmapped: Vec<String>,
+}
+
+impl Converter {
+    fn set_color(&mut self, key: &str, group: &str, background: bool, text: &str) {
+        match parse_color(text, &Palette::new()) {
+            Ok(color) => {
+                let attr = if background { "bg" } else { "fg" };
+                let value = Value::from(color_to_string(color));
+                self.groups
+                    .entry(group.to_string())
+                    .or_default()
+                    .insert(attr.to_string(), value);
+            }
+            Err(_) => self
+                .unmapped
+                .push(format!("{} (invalid color {})", key, text)),
+        }
+    }
+
+    /// Sets the colors and `fontStyle` of a rule for a scope to its group.
+    fn set_rule(&mut self, scope: &str, settings: &Map<String, Value>) {
+        // the last scope of a selector like `source.rust string` is the one colored
+        let scope = scope.split_whitespace().last().unwrap_or_default();
+        let mapped = SCOPES
+            .iter()
+            .find(|(prefix, _)| scope == *prefix || scope.starts_with(&format!("{}.", prefix)));
+        let (prefix, group) = match mapped {
+            Some(mapped) => mapped,
+            None => return self.unmapped.push(scope.to_string()),
+        };
+        let depth = scope.split('.').count() - prefix.split('.').count();
+        // `string` wins over a later `string.quoted.docstring`
+        if self.depth.get(*group).is_some_and(|d| *d < depth) {
+            return;
+        }
+        self.depth.insert(group.to_string(), depth);
+        for (key, background) in &[("foreground", false), ("background", true)] {
+            if let Some(text) = settings.get(*key).and_then(Value::as_str) {
+                self.set_color(scope, group, *background, text);
+            }
+        }
+        if let Some(style) = settings.get("fontStyle").and_then(Value::as_str) {
+            let attrs = self.groups.entry(group.to_string()).or_default();
+            for flag in &["bold", "italic", "underline"] {
+                attrs.insert(flag.to_string(), Value::from(style.contains(flag)));
+            }
+        }
+    }
+
+    /// TextMate rules, either `tokenColors` of VS Code or `settings` of a `.tmTheme`.
+    fn set_rules(&mut self, rules: &[Value]) {
+        for rule in rules {
+            let settings = match rule.get("settings").and_then(Value::as_object) {
+                Some(settings) => settings,
+                None => continue,
+            };
+            let scopes: Vec<String> = match rule.get("scope") {
+                Some(Value::String(scopes)) => scopes.split(',').map(String::from).collect(),
+                Some(Value::Array(scopes)) => scopes
+                    .iter()
+                    .filter_map(|s| s.as_str().map(String::from))
+                    .collect(),
+                _ => {
+                    self.set_globals(settings);
+                    continue;
+                }
+            };
+            for scope in scopes {
+                self.set_rule(scope.trim(), settings);
+            }
+        }
+    }
+
+    fn set_globals(&mut self, settings: &Map<String, Value>) {
+        for (key, value) in settings {
+            let text = value.as_str().unwrap_or_default();
+            match TEXTMATE_GLOBALS.iter().find(|(name, _, _)| name == key) {
+                Some((_, group, background)) => self.set_color(key, group, *background, text),
+                None => self.unmapped.push(key.to_string()),
+            }
+        }
+    }
+
+    fn finish(self, palette: Option<Map<String, Value>>) -> Imported {
+        let mut scheme = Map::new();
+        if let Some(palette) = palette {
+            scheme.insert(String::from("palette"), Value::Object(palette));
+        }
+        let groups = self
+            .groups
+            .into_iter()
+            .map(|(g, attrs)| (g, Value::Object(attrs)));
+        scheme.insert(String::from("groups"), Value::Object(groups.collect()));
+        Imported {
+            scheme: Value::Object(scheme),
+            unmapped: self.unmapped,
+        }
+    }
+}
+
+/// Converts a VS Code color theme, which may have comments and trailing commas.
+pub fn from_vscode(text: &str) -> Result<Imported, String> {
+    let theme: Map<String, Value> =
+        serde_json::from_str(&strip_jsonc(text)).map_err(|e| e.to_string())?;
+    let mut converter = Converter::default();
+    for (key, value) in &theme {
+        match (key.as_str(), value) {
+            ("name" | "type" | "$schema", _) => (),
+            ("colors", Value::Object(colors)) => {
+                for (key, value) in colors {
+                    let text = value.as_str().unwrap_or_default();
+                    match VSCODE_COLORS.iter().find(|(name, _, _)| name == key) {
+                        Some((_, group, background)) => {
+                            converter.set_color(key, group, *background, text)
+                        }
+                        None => converter.unmapped.push(key.to_string()),
+                    }
+                }
+            }
+            ("tokenColors", Value::Array(rules)) => converter.set_rules(rules),
+            _ => converter.unmapped.push(key.to_string()),
+        }
+    }
+    Ok(converter.finish(None))
+}
+
+/// Converts a TextMate `.tmTheme` property list.
+pub fn from_textmate(text: &str) -> Result<Imported, String> {
+    let theme = parse_plist(text)?;
+    let rules = theme
+        .get("settings")
+        .and_then(Value::as_array)
+        .ok_or("No settings in the theme")?;
+    let mut converter = Converter::default();
+    converter.set_rules(rules);
+    Ok(converter.finish(None))
+}
+
+/// Converts a base16 scheme, keeping its sixteen colors as the palette.
+pub fn from_base16(text: &str) -> Result<Imported, String> {
+    let mut palette = Map::new();
+    let mut unmapped = Vec::new();
+    for line in text.lines().map(str::trim) {
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) if !line.starts_with('#') => (key.trim(), value.trim()),
+            _ => continue,
+        };
+        let value = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split('"').next().unwrap_or_default(),
+            None => value.split(" #").next().unwrap_or_default().trim(),
+        };
+        let is_base = key.len() == 6 && key.starts_with("base0");
+        if is_base {
+            let color =
+                parse_color(value, &Palette::new()).map_err(|e| format!("{} in {}", e, key))?;
+            palette.insert(key.to_string(), Value::from(color_to_string(color)));
+        } else if !BASE16_INFO.contains(&key) {
+            unmapped.push(key.to_string());
+        }
+    }
+    if palette.len() != 16 {
+        return Err(format!(
+            "Expected base00 to base0F but got {} colors",
+            palette.len()
+        ));
+    }
+    let mut converter = Converter {
+        unmapped,
+        ..Converter::default()
+    };
+    for (group, fg, bg) in BASE16_GROUPS {
+        let attrs = converter.groups.entry(group.to_string()).or_default();
+        for (attr, base) in &[("fg", fg), ("bg", bg)] {
+            if let Some(base) = base {
+                attrs.insert(attr.to_string(), json!(format!("${}", base)));
+            }
+        }
+    }
+    Ok(converter.finish(Some(palette)))
+}
+
+/// Converts a theme, telling the format by the extension or else by the contents.
+pub fn convert(path: &Path, text: &str) -> Result<Imported, String> {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("tmtheme") | Some("plist") => from_textmate(text),
+        Some("yaml") | Some("yml") => from_base16(text),
+        Some("json") | Some("jsonc") => from_vscode(text),
+        _ if text.trim_start().starts_with('<') => from_textmate(text),
+        _ if text.contains("base00") => from_base16(text),
+        _ => from_vscode(text),
+    }
+}
+
+/// `My Theme.tmTheme` becomes `my-theme`.
+fn scheme_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map_or_else(String::new, |s| s.to_string_lossy().to_string());
+    let name: String = stem
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    name.trim_matches('-').to_string()
+}
+
+/// Converts the theme into `colors/` of the config directory, named after the
+/// file unless `name` is given, and returns the new scheme with what was left out.
+pub fn import_file(source: &str, name: Option<&str>) -> Result<(PathBuf, Vec<String>), String> {
+    let path = Path::new(source);
+    let text = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", source, e))?;
+    let imported =
+        convert(path, &text).map_err(|e| format!("Unable to import {}: {}", source, e))?;
+    let dir = file_assist::config_dir()
+        .map(|dir| dir.join("colors"))
+        .ok_or("No config directory to import the theme into")?;
+    let name = name.map_or_else(|| scheme_name(path), String::from);
+    let target = dir.join(format!("{}.json", name.trim_end_matches(".json")));
+    if target.exists() {
+        return Err(format!("{} already exists", target.display()));
+    }
+    let json = serde_json::to_string_pretty(&imported.scheme).map_err(|e| e.to_string())?;
+    fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&target, json + "\n"))
+        .map_err(|e| format!("Unable to write {}: {}", target.display(), e))?;
+    Ok((target, imported.unmapped))
+}
+
+/// JSON without `//` and `/* */` comments and trailing commas.
+fn strip_jsonc(text: &str) -> String {
+    let mut json = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_string = !in_string,
+            '\\' if in_string => {
+                json.push(c);
+                json.extend(chars.next());
+                continue;
+            }
+            '/' if !in_string && chars.peek() == Some(&'/') => {
+                chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+                json.push('\n');
+                continue;
+            }
+            '/' if !in_string && chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+                continue;
+            }
+            '}' | ']' if !in_string => {
+                let trimmed = json.trim_end().len();
+                if json[..trimmed].ends_with(',') {
+                    json.truncate(trimmed - 1);
+                }
+            }
+            _ => (),
+        }
+        json.push(c);
+    }
+    json
+}
+
+/// Parts of a property list: `<tag>`, `</tag>`, `<tag/>` and the text between them.
+#[derive(PartialEq, Debug)]
+enum Token<'a> {
+    Open(&'a str),
+    Close(&'a str),
+    Empty(&'a str),
+    Text(String),
+}
+
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        loop {
+            let start = self.rest.find('<')?;
+            let text = &self.rest[..start];
+            if !text.trim().is_empty() {
+                self.rest = &self.rest[start..];
+                return Some(Token::Text(unescape(text)));
+            }
+            let tag = &self.rest[start + 1..];
+            // declarations, doctype and comments
+            let end = match tag.strip_prefix("!--") {
+                Some(comment) => comment.find("-->")? + 6,
+                None => tag.find('>')? + 1,
+            };
+            self.rest = &tag[end..];
+            let tag = tag[..end - 1].trim();
+            let name = |tag: &'a str| tag.split_whitespace().next().unwrap_or_default();
+            if tag.starts_with('!') || tag.starts_with('?') {
+                continue;
+            }
+            return Some(match (tag.strip_prefix('/'), tag.strip_suffix('/')) {
+                (Some(tag), _) => Token::Close(name(tag)),
+                (_, Some(tag)) => Token::Empty(name(tag)),
+                _ => Token::Open(name(tag)),
+            });
+        }
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Reads an XML property list into JSON: dictionaries, arrays, strings and booleans.
+fn parse_plist(text: &str) -> Result<Value, String> {
+    let mut tokens = Tokens { rest: text };
+    loop {
+        match tokens.next() {
+            Some(Token::Open("plist")) => (),
+            Some(Token::Open(tag)) => return plist_value(&mut tokens, tag),
+            Some(Token::Empty(tag)) => return empty_value(tag),
+            Some(_) => (),
+            None => return Err(String::from("Not a property list")),
+        }
+    }
+}
+
+fn empty_value(tag: &str) -> Result<Value, String> {
+    match tag {
+        "true" => Ok(Value::from(true)),
+        "false" => Ok(Value::from(false)),
+        "dict" => Ok(Value::Object(Map::new())),
+        "array" => Ok(Value::Array(Vec::new())),
+        _ => Ok(Value::from("")),
+    }
+}
+
+fn plist_value(tokens: &mut Tokens, tag: &str) -> Result<Value, String> {
+    let unexpected = |token: Option<Token>| format!("Unexpected {:?} in <{}>", token, tag);
+    match tag {
+        "dict" => {
+            let mut dict = Map::new();
+            loop {
+                let key = match tokens.next() {
+                    Some(Token::Close("dict")) => return Ok(Value::Object(dict)),
+                    Some(Token::Open("key")) => plist_value(tokens, "key")?,
+                    other => return Err(unexpected(other)),
+                };
+                let value = match tokens.next() {
+                    Some(Token::Open(tag)) => plist_value(tokens, tag)?,
+                    Some(Token::Empty(tag)) => empty_value(tag)?,
+                    other => return Err(unexpected(other)),
+                };
+                dict.insert(key.as_str().unwrap_or_default().to_string(), value);
+            }
+        }
+        "array" => {
+            let mut array = Vec::new();
+            loop {
+                match tokens.next() {
+                    Some(Token::Close("array")) => return Ok(Value::Array(array)),
+                    Some(Token::Open(tag)) => array.push(plist_value(tokens, tag)?),
+                    Some(Token::Empty(tag)) => array.push(empty_value(tag)?),
+                    other => return Err(unexpected(other)),
+                }
+            }
+        }
+        _ => {
+            let text = match tokens.next() {
+                Some(Token::Text(text)) => match tokens.next() {
+                    Some(Token::Close(close)) if close == tag => text,
+                    other => return Err(unexpected(other)),
+                },
+                Some(Token::Close(close)) if close == tag => String::new(),
+                other => return Err(unexpected(other)),
+            };
+            Ok(Value::from(text.trim()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group<'a>(imported: &'a Imported, name: &str) -> &'a Value {
+        &imported.scheme["groups"][name]
+    }
+
+    #[test]
+    fn vscode_test() {
+        let theme = r##"{
+            // comments and trailing commas are allowed
+            "name": "Test",
+            "colors": {
+                "editor.background": "#1e1e1e",
+                "editor.foreground": "#d4d4d4", /* inline */
+                "editor.selectionBackground": "#264f7880",
+                "activityBar.background": "#333333",
+            },
+            "tokenColors": [
+                {"scope": ["comment", "punctuation.definition.comment"], "settings": {"foreground": "#6A9955", "fontStyle": "italic"}},
+                {"scope": "string, string.quoted.docstring", "settings": {"foreground": "#ce9178"}},
+                {"scope": "string.quoted.docstring", "settings": {"foreground": "#ffffff"}},
+                {"scope": "source.rust keyword.control", "settings": {"foreground": "#C586C0"}},
+            ],
+        }"##;
+        let imported = from_vscode(theme).unwrap();
+        assert_eq!(
+            json!({"fg": "#D4D4D4", "bg": "#1E1E1E"}),
+            *group(&imported, "Normal")
+        );
+        assert_eq!(json!({"bg": "#264F7880"}), *group(&imported, "Visual"));
+        assert_eq!(
+            json!({"fg": "#6A9955", "bold": false, "italic": true, "underline": false}),
+            *group(&imported, "Comment")
+        );
+        assert_eq!(json!({"fg": "#CE9178"}), *group(&imported, "String"));
+        assert_eq!(json!({"fg": "#C586C0"}), *group(&imported, "Keyword"));
+        assert_eq!(
+            vec!["activityBar.background", "punctuation.definition.comment"],
+            imported.unmapped
+        );
+    }
+
+    #[test]
+    fn textmate_test() {
+        let theme = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>Test &amp; More</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>background</key>
+                <string>#272822</string>
+                <key>caret</key>
+                <string>#F8F8F0</string>
+                <key>invisibles</key>
+                <string>#3B3A32</string>
+            </dict>
+        </dict>
+        <!-- a rule -->
+        <dict>
+            <key>scope</key>
+            <string>keyword</string>
+            <key>settings</key>
+            <dict>
+                <key>foreground</key>
+                <string>#F92672</string>
+                <key>fontStyle</key>
+                <string>bold</string>
+            </dict>
+        </dict>
+    </array>
+    <key>semanticClass</key>
+    <true/>
+</dict>
+</plist>"#;
+        let plist = parse_plist(theme).unwrap();
+        assert_eq!(json!("Test & More"), plist["name"]);
+        assert_eq!(json!(true), plist["semanticClass"]);
+        let imported = from_textmate(theme).unwrap();
+        assert_eq!(json!({"bg": "#272822"}), *group(&imported, "Normal"));
+        assert_eq!(json!({"bg": "#F8F8F0"}), *group(&imported, "Cursor"));
+        assert_eq!(json!(true), group(&imported, "Keyword")["bold"]);
+        assert_eq!(vec!["invisibles"], imported.unmapped);
+        assert!(parse_plist("<plist><dict><string>x</string></dict></plist>").is_err());
+    }
+
+    #[test]
+    fn base16_test() {
+        let mut theme = String::from("scheme: \"Test\"\nauthor: \"Me\" # comment\nextra: 1\n");
+        for i in 0..16 {
+            theme += &format!("base0{:X}: \"{:02x}{:02x}{:02x}\"\n", i, i, i, i);
+        }
+        let imported = from_base16(&theme).unwrap();
+        assert_eq!(json!("#0F0F0F"), imported.scheme["palette"]["base0F"]);
+        assert_eq!(
+            json!({"fg": "$base05", "bg": "$base00"}),
+            *group(&imported, "Normal")
+        );
+        assert_eq!(vec!["extra"], imported.unmapped);
+        assert!(from_base16("base00: \"000000\"").is_err());
+    }
+
+    #[test]
+    fn scheme_name_test() {
+        assert_eq!("my-theme", scheme_name(Path::new("/tmp/My Theme.tmTheme")));
+        assert_eq!("one-dark", scheme_name(Path::new("One_Dark.json")));
+    }
+}
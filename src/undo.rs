@@ -0,0 +1,286 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::file_assist;
+
+/// Most undo steps kept per buffer.
+const UNDO_LEVELS: usize = 1000;
+
+/// Most text, in bytes, of the undo steps written to an undo file.
+const UNDOFILE_SIZE: usize = 1 << 20;
+
+/// Lines replaced by one undo step, with the cursor before and after it.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Change {
+    /// First changed row.
+    pub row: usize,
+    pub old: Vec<String>,
+    pub new: Vec<String>,
+    pub before: (usize, usize),
+    pub after: (usize, usize),
+}
+
+impl Change {
+    /// The rows that differ between `old` and `new`, if any.
+    pub fn between(
+        old: &[String],
+        new: &[String],
+        before: (usize, usize),
+        after: (usize, usize),
+    ) -> Option<Change> {
+        if old == new {
+            return None;
+        }
+        let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        Some(Change {
+            row: prefix,
+            old: old[prefix..old.len() - suffix].to_vec(),
+            new: new[prefix..new.len() - suffix].to_vec(),
+            before,
+            after,
+        })
+    }
+
+    /// Puts the old lines back in `lines`.
+    pub fn revert(&self, lines: &mut Vec<String>) {
+        let end = self.row + self.new.len();
+        lines.splice(self.row..end, self.old.iter().cloned());
+    }
+
+    /// Puts the new lines back in `lines`.
+    pub fn apply(&self, lines: &mut Vec<String>) {
+        let end = self.row + self.old.len();
+        lines.splice(self.row..end, self.new.iter().cloned());
+    }
+
+    fn size(&self) -> usize {
+        self.old
+            .iter()
+            .chain(&self.new)
+            .map(|line| line.len() + 1)
+            .sum()
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct History {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+    /// Number of undo steps when the buffer matched the file, if still reachable.
+    saved: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            saved: Some(0),
+        }
+    }
+}
+
+impl History {
+    pub fn push(&mut self, change: Change) {
+        if self.saved.is_some_and(|saved| saved > self.undo.len()) {
+            self.saved = None;
+        }
+        self.undo.push(change);
+        if self.undo.len() > UNDO_LEVELS {
+            self.undo.remove(0);
+            self.saved = self.saved.and_then(|saved| saved.checked_sub(1));
+        }
+        self.redo.clear();
+    }
+
+    /// Moves the last change to the redo steps, for the caller to revert.
+    pub fn undo(&mut self) -> Option<&Change> {
+        let change = self.undo.pop()?;
+        self.redo.push(change);
+        self.redo.last()
+    }
+
+    /// Moves the last undone change back, for the caller to apply.
+    pub fn redo(&mut self) -> Option<&Change> {
+        let change = self.redo.pop()?;
+        self.undo.push(change);
+        self.undo.last()
+    }
+
+    /// Marks the current state as the one written to the file.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.undo.len());
+    }
+
+    /// Whether undo or redo led back to the state written to the file.
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.undo.len())
+    }
+
+    /// The newest undo steps whose text fits in `max_size`. Redo steps aren't kept.
+    fn newest(&self, max_size: usize) -> History {
+        let mut size = 0;
+        let keep = self
+            .undo
+            .iter()
+            .rev()
+            .take_while(|change| {
+                size += change.size();
+                size <= max_size
+            })
+            .count();
+        let dropped = self.undo.len() - keep;
+        History {
+            undo: self.undo[dropped..].to_vec(),
+            redo: Vec::new(),
+            saved: self.saved.and_then(|saved| saved.checked_sub(dropped)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct UndoFile {
+    hash: u64,
+    history: History,
+}
+
+/// FNV-1a, stable across Rust versions unlike `DefaultHasher`.
+fn content_hash(lines: &[String]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for (i, line) in lines.iter().enumerate() {
+        let separator: &[u8] = if i > 0 { b"\n" } else { b"" };
+        for byte in separator.iter().chain(line.as_bytes()) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// Saves the history of `file`, whose contents on disk are now `lines`.
+pub fn save(file: &str, lines: &[String], history: &History) -> Result<(), String> {
+    let path = file_assist::state_file("undo", file, "json")
+        .ok_or_else(|| String::from("No state directory for the undo file"))?;
+    let undo_file = UndoFile {
+        hash: content_hash(lines),
+        history: history.newest(UNDOFILE_SIZE),
+    };
+    let json = serde_json::to_string(&undo_file).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(&path, json).map_err(|e| format!("Unable to write undo file: {}", e))
+}
+
+/// History saved with the same contents as `lines`. A history saved for other
+/// contents is stale and gets deleted.
+pub fn load(file: &str, lines: &[String]) -> Option<History> {
+    let path = file_assist::state_file("undo", file, "json")?;
+    let json = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str::<UndoFile>(&json) {
+        Ok(undo_file) if undo_file.hash == content_hash(lines) => Some(undo_file.history),
+        _ => {
+            let _ = fs::remove_file(&path);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(String::from).collect()
+    }
+
+    /// Records the change from `old` to `new` and returns `new`.
+    fn edit(history: &mut History, old: &[String], new: &str) -> Vec<String> {
+        let new = lines(new);
+        history.push(Change::between(old, &new, (0, 0), (0, 0)).unwrap());
+        new
+    }
+
+    #[test]
+    fn between_test() {
+        let old = lines("a\nb\nc\nd");
+        assert_eq!(None, Change::between(&old, &old, (0, 0), (0, 0)));
+        let new = lines("a\nx\ny\nd");
+        let change = Change::between(&old, &new, (1, 0), (2, 1)).unwrap();
+        assert_eq!(
+            (1, lines("b\nc"), lines("x\ny")),
+            (change.row, change.old.clone(), change.new.clone())
+        );
+        let mut text = new.clone();
+        change.revert(&mut text);
+        assert_eq!(old, text);
+        change.apply(&mut text);
+        assert_eq!(new, text);
+        let joined = lines("a\na");
+        let change = Change::between(&lines("a\na\na"), &joined, (0, 0), (0, 0)).unwrap();
+        assert_eq!(
+            (2, lines("a"), vec![]),
+            (change.row, change.old, change.new)
+        );
+    }
+
+    #[test]
+    fn undo_redo_test() {
+        let mut history = History::default();
+        assert_eq!(None, history.undo());
+        let ab = edit(&mut history, &lines("a"), "ab");
+        edit(&mut history, &ab, "abc");
+        assert_eq!(lines("abc"), history.undo().unwrap().new);
+        assert_eq!(lines("ab"), history.undo().unwrap().new);
+        assert_eq!(None, history.undo());
+        assert_eq!(lines("ab"), history.redo().unwrap().new);
+        edit(&mut history, &ab, "x");
+        assert_eq!(None, history.redo());
+    }
+
+    #[test]
+    fn saved_test() {
+        let mut history = History::default();
+        let ab = edit(&mut history, &lines("a"), "ab");
+        assert!(!history.is_saved());
+        history.undo();
+        assert!(history.is_saved());
+        history.redo();
+        history.mark_saved();
+        history.undo();
+        edit(&mut history, &lines("a"), "x");
+        history.undo();
+        assert!(!history.is_saved());
+        edit(&mut history, &ab, "abc");
+        assert!(!history.is_saved());
+    }
+
+    #[test]
+    fn newest_test() {
+        let mut history = History::default();
+        let mut text = lines("");
+        for line in ["a", "b", "c"].iter() {
+            text = edit(&mut history, &text, line);
+        }
+        history.mark_saved();
+        let newest = history.newest(4);
+        assert_eq!(1, newest.undo.len());
+        assert_eq!(lines("c"), newest.undo[0].new);
+        assert!(newest.is_saved());
+        assert!(history.newest(0).is_saved());
+    }
+
+    #[test]
+    fn content_hash_test() {
+        let a = lines("ab\nc");
+        let b = lines("a\nbc");
+        assert_ne!(content_hash(&a), content_hash(&b));
+        assert_eq!(content_hash(&a), content_hash(&a.clone()));
+    }
+}
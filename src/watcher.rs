@@ -0,0 +1,156 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(target_os = "linux")]
+use inotify::{Inotify, WatchMask};
+
+/// Without inotify the file is checked this often.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What identifies a version of a file on disk.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    inode: u64,
+    len: u64,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Option<Stamp> {
+        let metadata = fs::metadata(path).ok()?;
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(&metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Some(Stamp {
+            modified: metadata.modified().ok(),
+            inode,
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Notices when the open file is changed by another program.
+pub struct FileWatcher {
+    path: PathBuf,
+    stamp: Option<Stamp>,
+    last_check: Instant,
+    #[cfg(target_os = "linux")]
+    inotify: Option<Inotify>,
+}
+
+impl FileWatcher {
+    /// Watches the file's directory rather than the file, so files replaced by a
+    /// rename (git checkout, atomic saves) are still noticed.
+    pub fn new(path_name: &str) -> FileWatcher {
+        let path = PathBuf::from(path_name);
+        #[cfg(target_os = "linux")]
+        let inotify = Inotify::init().ok().and_then(|inotify| {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let mask = WatchMask::CLOSE_WRITE
+                | WatchMask::MOVED_TO
+                | WatchMask::CREATE
+                | WatchMask::DELETE;
+            inotify.watches().add(dir, mask).ok()?;
+            Some(inotify)
+        });
+        FileWatcher {
+            stamp: Stamp::of(&path),
+            path,
+            last_check: Instant::now(),
+            #[cfg(target_os = "linux")]
+            inotify,
+        }
+    }
+
+    /// Accepts the file as it is now on disk, e.g. after saving or reloading it.
+    pub fn reset(&mut self) {
+        self.stamp = Stamp::of(&self.path);
+    }
+
+    /// Whether the file differs from what was last accepted.
+    pub fn is_stale(&self) -> bool {
+        Stamp::of(&self.path) != self.stamp
+    }
+
+    /// True once for every change on disk since the last call or `reset`.
+    pub fn changed(&mut self) -> bool {
+        if !self.events_pending() {
+            return false;
+        }
+        if self.is_stale() {
+            self.reset();
+            true
+        } else {
+            false
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn events_pending(&mut self) -> bool {
+        let name = self.path.file_name().map(|n| n.to_os_string());
+        match &mut self.inotify {
+            Some(inotify) => {
+                let mut buffer = [0; 4096];
+                let mut pending = false;
+                while let Ok(events) = inotify.read_events(&mut buffer) {
+                    let mut any = false;
+                    for event in events {
+                        any = true;
+                        pending |= event.name.map(|n| n.to_os_string()) == name;
+                    }
+                    if !any {
+                        break;
+                    }
+                }
+                pending
+            }
+            None => self.poll_due(),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn events_pending(&mut self) -> bool {
+        self.poll_due()
+    }
+
+    fn poll_due(&mut self) -> bool {
+        if self.last_check.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_test() {
+        let dir = std::env::temp_dir().join(format!("rslush-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("watched.txt");
+        fs::write(&path, "one\n").unwrap();
+        let mut watcher = FileWatcher::new(path.to_str().unwrap());
+        assert!(!watcher.changed());
+
+        // replaced by a rename, like git does
+        let tmp = dir.join("watched.tmp");
+        fs::write(&tmp, "two lines\n").unwrap();
+        fs::rename(&tmp, &path).unwrap();
+        std::thread::sleep(POLL_INTERVAL);
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::write(&path, "three\n").unwrap();
+        assert!(watcher.is_stale());
+        watcher.reset();
+        assert!(!watcher.is_stale());
+    }
+}